@@ -0,0 +1,358 @@
+use crate::structs::{AccountInput, RetirementInput};
+use crate::tax::{TaxSchedule, TaxYear};
+
+pub const TAXABLE: usize = 0;
+pub const TAX_DEFERRED: usize = 1;
+pub const TAX_FREE: usize = 2;
+const CONVENTIONAL_ORDER: [usize; 3] = [TAXABLE, TAX_DEFERRED, TAX_FREE];
+// Heirs are assumed to empty inherited tax-deferred accounts evenly over ten years.
+const LIQUIDATION_YEARS: f64 = 10.0;
+
+pub enum WithdrawalOrder {
+    Conventional,
+    Proportional,
+    // Yearly tax-deferred draw first: a fixed amount, or up to the top of the bracket
+    // taxed at `rate` when a tax schedule is set.
+    BracketFill { amount: f64, rate: Option<f64> },
+}
+
+pub struct AccountModel {
+    monthly_drag_factors: [f64; 3],
+    withdrawal_tax_rates: [f64; 3],
+    pub order: WithdrawalOrder,
+    contribution_account: usize,
+    pub tax_schedule: Option<TaxSchedule>,
+}
+
+/// Real balances of the taxable, tax-deferred and tax-free accounts on one path.
+#[derive(Clone, Debug)]
+pub struct AccountBalances {
+    pub balances: [f64; 3],
+    pub taxable_basis: f64,
+    pub taxes_paid: f64,
+    pub tax_year: TaxYear,
+    year_tax_deferred_withdrawals: f64,
+}
+
+pub fn account_index(name: &str) -> Option<usize> {
+    match name {
+        "taxable" => Some(TAXABLE),
+        "taxDeferred" => Some(TAX_DEFERRED),
+        "taxFree" => Some(TAX_FREE),
+        _ => None,
+    }
+}
+
+impl AccountModel {
+    pub fn from_input(input: &RetirementInput) -> Option<Self> {
+        let accounts = input.accounts.as_ref()?;
+        let inputs = [
+            &accounts.taxable,
+            &accounts.tax_deferred,
+            &accounts.tax_free,
+        ];
+
+        let monthly_drag_factors = inputs.map(|account| {
+            let annual_drag = account
+                .as_ref()
+                .and_then(|account| account.annual_drag)
+                .unwrap_or(0.0)
+                .clamp(0.0, 1.0);
+            1.0 - annual_drag / 12.0
+        });
+        let withdrawal_tax_rates = inputs.map(|account| {
+            account
+                .as_ref()
+                .and_then(|account| account.withdrawal_tax_rate)
+                .unwrap_or(0.0)
+                .clamp(0.0, 0.99)
+        });
+
+        let order = match accounts
+            .withdrawal_order
+            .as_deref()
+            .unwrap_or("conventional")
+        {
+            "proportional" => WithdrawalOrder::Proportional,
+            "bracketFill" => WithdrawalOrder::BracketFill {
+                amount: accounts.bracket_fill_amount.unwrap_or(0.0).max(0.0),
+                rate: accounts.bracket_fill_rate,
+            },
+            _ => WithdrawalOrder::Conventional,
+        };
+
+        Some(Self {
+            monthly_drag_factors,
+            withdrawal_tax_rates,
+            order,
+            contribution_account: accounts
+                .contribution_account
+                .as_deref()
+                .and_then(account_index)
+                .unwrap_or(TAXABLE),
+            tax_schedule: input.tax_schedule.as_ref().map(TaxSchedule::from_input),
+        })
+    }
+
+    fn withdrawal_tax_rate(&self, ledger: &AccountBalances, account: usize) -> f64 {
+        if account == TAXABLE {
+            // Only the gain share of a taxable sale is taxed.
+            self.withdrawal_tax_rates[TAXABLE] * ledger.taxable_gain_fraction()
+        } else {
+            self.withdrawal_tax_rates[account]
+        }
+    }
+}
+
+pub fn starting_balance(input: &RetirementInput) -> f64 {
+    match &input.accounts {
+        Some(_) => AccountBalances::new(input).total(),
+        None => input.current_savings,
+    }
+}
+
+impl AccountBalances {
+    pub fn new(input: &RetirementInput) -> Self {
+        let balance_of = |account: &Option<AccountInput>| {
+            account
+                .as_ref()
+                .map_or(0.0, |account| account.balance.max(0.0))
+        };
+        let (balances, taxable_basis) = match &input.accounts {
+            Some(accounts) => {
+                let taxable = balance_of(&accounts.taxable);
+                let basis = accounts
+                    .taxable
+                    .as_ref()
+                    .and_then(|account| account.cost_basis)
+                    .unwrap_or(taxable)
+                    .max(0.0);
+                (
+                    [
+                        taxable,
+                        balance_of(&accounts.tax_deferred),
+                        balance_of(&accounts.tax_free),
+                    ],
+                    basis,
+                )
+            }
+            None => ([input.current_savings, 0.0, 0.0], input.current_savings),
+        };
+
+        Self {
+            balances,
+            taxable_basis,
+            taxes_paid: 0.0,
+            tax_year: TaxYear::new(),
+            year_tax_deferred_withdrawals: 0.0,
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.balances.iter().sum()
+    }
+
+    fn taxable_gain_fraction(&self) -> f64 {
+        let taxable = self.balances[TAXABLE];
+        if taxable <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - self.taxable_basis / taxable).clamp(0.0, 1.0)
+    }
+
+    pub fn start_year(&mut self, schedule: Option<&TaxSchedule>, price_index: f64) {
+        self.year_tax_deferred_withdrawals = 0.0;
+        if let Some(schedule) = schedule {
+            self.tax_year.start(schedule, price_index);
+        }
+    }
+
+    /// Records taxable income received outside the portfolio and returns the tax due on it.
+    pub fn tax_income(&mut self, schedule: &TaxSchedule, amount: f64) -> f64 {
+        if amount <= 0.0 {
+            return 0.0;
+        }
+        let tax = self.tax_year.add(schedule, amount, 0.0);
+        self.taxes_paid += tax;
+        tax
+    }
+
+    pub fn deposit(&mut self, model: &AccountModel, amount: f64) {
+        self.balances[model.contribution_account] += amount;
+        if model.contribution_account == TAXABLE {
+            self.taxable_basis += amount;
+        }
+    }
+
+    /// Salary contributions and employer money paid straight into each account.
+    pub fn contribute(&mut self, amounts: [f64; 3]) {
+        for (balance, amount) in self.balances.iter_mut().zip(amounts) {
+            *balance += amount;
+        }
+        self.taxable_basis += amounts[TAXABLE];
+    }
+
+    /// Sells enough of `account` to net `amount` after tax, capped at `gross_cap` before tax.
+    /// Returns the part of `amount` left unfunded.
+    fn withdraw_from(
+        &mut self,
+        model: &AccountModel,
+        account: usize,
+        amount: f64,
+        gross_cap: f64,
+    ) -> f64 {
+        let available = self.balances[account].min(gross_cap);
+        if amount <= 0.0 || available <= 0.0 {
+            return amount.max(0.0);
+        }
+
+        let (gross, tax) = match &model.tax_schedule {
+            Some(schedule) if account != TAX_FREE => {
+                let (ordinary_share, gains_share) = if account == TAX_DEFERRED {
+                    (1.0, 0.0)
+                } else {
+                    (0.0, self.taxable_gain_fraction())
+                };
+                let gross = self
+                    .tax_year
+                    .gross_up(schedule, amount, ordinary_share, gains_share)
+                    .min(available);
+                let tax = self
+                    .tax_year
+                    .add(schedule, gross * ordinary_share, gross * gains_share);
+                (gross, tax)
+            }
+            _ => {
+                let tax_rate = model.withdrawal_tax_rate(self, account);
+                let gross = (amount / (1.0 - tax_rate)).min(available);
+                (gross, gross * tax_rate)
+            }
+        };
+
+        if account == TAXABLE {
+            self.taxable_basis -= self.taxable_basis * gross / self.balances[TAXABLE];
+        }
+        if account == TAX_DEFERRED {
+            self.year_tax_deferred_withdrawals += gross;
+        }
+        self.balances[account] -= gross;
+        self.taxes_paid += tax;
+        (amount - (gross - tax)).max(0.0)
+    }
+
+    pub fn withdraw(&mut self, model: &AccountModel, amount: f64) -> f64 {
+        let mut remaining = amount;
+        match model.order {
+            WithdrawalOrder::Conventional => {}
+            WithdrawalOrder::Proportional => {
+                let total = self.total();
+                if total > 0.0 {
+                    let shares = self.balances.map(|balance| balance / total);
+                    remaining = 0.0;
+                    for account in CONVENTIONAL_ORDER {
+                        remaining +=
+                            self.withdraw_from(model, account, amount * shares[account], f64::MAX);
+                    }
+                }
+            }
+            WithdrawalOrder::BracketFill { amount, rate } => {
+                let ceiling = model
+                    .tax_schedule
+                    .as_ref()
+                    .zip(rate)
+                    .and_then(|(schedule, rate)| schedule.bracket_ceiling(rate));
+                let room = match ceiling {
+                    Some(ceiling) => {
+                        ceiling * self.tax_year.threshold_scale() - self.tax_year.ordinary_income
+                    }
+                    None => amount - self.year_tax_deferred_withdrawals,
+                }
+                .max(0.0);
+                remaining = self.withdraw_from(model, TAX_DEFERRED, remaining, room);
+            }
+        }
+
+        for account in CONVENTIONAL_ORDER {
+            remaining = self.withdraw_from(model, account, remaining, f64::MAX);
+        }
+        remaining
+    }
+
+    /// Moves up to `amount` from the tax-deferred to the tax-free account, taxed as ordinary
+    /// income. The tax is paid from the taxable account, or withheld from the conversion
+    /// when that runs dry.
+    pub fn convert(&mut self, model: &AccountModel, amount: f64) {
+        let amount = amount.min(self.balances[TAX_DEFERRED]);
+        if amount <= 0.0 {
+            return;
+        }
+        let tax = match &model.tax_schedule {
+            Some(schedule) => self.tax_year.add(schedule, amount, 0.0),
+            None => amount * model.withdrawal_tax_rates[TAX_DEFERRED],
+        };
+        self.balances[TAX_DEFERRED] -= amount;
+        self.balances[TAX_FREE] += amount;
+        self.taxes_paid += tax;
+
+        let unpaid = self.withdraw_from(model, TAXABLE, tax, f64::MAX);
+        self.balances[TAX_FREE] = (self.balances[TAX_FREE] - unpaid).max(0.0);
+    }
+
+    /// Realizes the gains on rebalancing sales of `sold_fraction` of the portfolio. Only the
+    /// taxable account owes tax; its cost basis steps up to the repurchase price.
+    pub fn realize_gains(&mut self, model: &AccountModel, sold_fraction: f64) {
+        let gain = self.balances[TAXABLE] * sold_fraction * self.taxable_gain_fraction();
+        if gain <= 0.0 {
+            return;
+        }
+        let tax = match &model.tax_schedule {
+            Some(schedule) => self.tax_year.add(schedule, 0.0, gain),
+            None => gain * model.withdrawal_tax_rates[TAXABLE],
+        };
+        self.taxable_basis += gain;
+        let taxable = self.balances[TAXABLE];
+        self.taxable_basis -= self.taxable_basis * (tax / taxable).min(1.0);
+        self.balances[TAXABLE] = (taxable - tax).max(0.0);
+        self.taxes_paid += tax;
+    }
+
+    /// Tax still embedded in the balances: unrealized taxable gains plus the tax-deferred
+    /// account, spread over `LIQUIDATION_YEARS` on top of no other income.
+    pub fn deferred_tax(&self, model: &AccountModel) -> f64 {
+        let gains = self.balances[TAXABLE] * self.taxable_gain_fraction();
+        let deferred = self.balances[TAX_DEFERRED];
+        match &model.tax_schedule {
+            Some(schedule) => {
+                LIQUIDATION_YEARS
+                    * schedule.annual_tax(
+                        deferred / LIQUIDATION_YEARS,
+                        gains / LIQUIDATION_YEARS,
+                        self.tax_year.threshold_scale(),
+                    )
+            }
+            None => {
+                deferred * model.withdrawal_tax_rates[TAX_DEFERRED]
+                    + gains * model.withdrawal_tax_rates[TAXABLE]
+            }
+        }
+    }
+
+    /// Applies one month's net cash flow and returns the unfunded shortfall.
+    pub fn apply_flow(&mut self, model: &AccountModel, net_flow: f64) -> f64 {
+        if net_flow >= 0.0 {
+            self.deposit(model, net_flow);
+            0.0
+        } else {
+            self.withdraw(model, -net_flow)
+        }
+    }
+
+    /// `real_growth_factor` is the path's portfolio growth after fees, deflated by inflation.
+    pub fn grow(&mut self, model: &AccountModel, real_growth_factor: f64, monthly_inflation: f64) {
+        for (balance, drag) in self.balances.iter_mut().zip(model.monthly_drag_factors) {
+            *balance = (*balance * real_growth_factor * drag).max(0.0);
+        }
+        // The cost basis is fixed in nominal terms, so it erodes in real terms.
+        self.taxable_basis = (self.taxable_basis / (1.0 + monthly_inflation)).max(0.0);
+    }
+}
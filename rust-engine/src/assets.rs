@@ -0,0 +1,747 @@
+use crate::calculations::{summarize, RandomSource};
+use crate::engine::{
+    clamp_monthly_return, cornish_fisher_score, GlidePathOutcome, RebalancingOutcome,
+};
+use crate::engine2::apply_moment_targeting;
+use crate::regimes::RegimeModel;
+use crate::replay::{AllocationPath, AllocationReplay};
+use crate::structs::{AssetInput, GlidePathInput, RebalancingInput, RetirementInput};
+
+pub const MIN_HISTORY_MONTHS: usize = 120;
+const DEFAULT_TOLERANCE_BAND: f64 = 0.05;
+const DEFAULT_GLIDE_YEARS_BEFORE: f64 = 20.0;
+const DEFAULT_GLIDE_YEARS_AFTER: f64 = 10.0;
+
+// Built-in glide paths for the comparison: a target-date fund that de-risks into and through
+// retirement, and a bond tent that halves equity at retirement and rebuilds it over 15 years.
+const TARGET_DATE_GLIDE: (f64, f64, f64, f64, f64) = (0.9, 0.5, 0.3, 25.0, 7.0);
+const BOND_TENT_YEARS: (f64, f64) = (10.0, 15.0);
+
+// Annual (mean, volatility) used when an asset gives no parametric moments of its own.
+const EQUITY_MOMENTS: (f64, f64) = (0.07, 0.16);
+const BOND_MOMENTS: (f64, f64) = (0.03, 0.06);
+const CASH_MOMENTS: (f64, f64) = (0.015, 0.01);
+const OTHER_MOMENTS: (f64, f64) = (0.05, 0.1);
+
+#[derive(Clone, Copy, PartialEq)]
+enum AssetClass {
+    Equity,
+    Bond,
+    Cash,
+    Other,
+}
+
+fn asset_class(id: &str) -> AssetClass {
+    match id.to_ascii_lowercase().as_str() {
+        "equity" | "equities" | "stock" | "stocks" => AssetClass::Equity,
+        "bond" | "bonds" => AssetClass::Bond,
+        "cash" | "bank" => AssetClass::Cash,
+        _ => AssetClass::Other,
+    }
+}
+
+pub struct Asset {
+    pub id: String,
+    pub weight: f64,
+    class: AssetClass,
+    mean: f64,
+    std: f64,
+    skewness: f64,
+    kurtosis: f64,
+    crisis_mean: f64,
+    crisis_std: f64,
+    // Explicit per-regime-state moments, indexed like the regime model's (sorted) states.
+    regime_means: Vec<Option<f64>>,
+    regime_stds: Vec<Option<f64>>,
+    history: Vec<f64>,
+}
+
+/// Assets simulated side by side. Historical returns are drawn for every asset from the same
+/// month, so a block bootstrap keeps their cross-correlation; parametric draws are correlated
+/// through the Cholesky factor of the correlation matrix.
+pub struct Portfolio {
+    pub assets: Vec<Asset>,
+    // Lower-triangular Cholesky factor of the asset correlation matrix.
+    cholesky: Vec<Vec<f64>>,
+    history_len: usize,
+    // Position in the input histories of each kept month.
+    history_months: Vec<usize>,
+    // Regime state that uses the crisis moments when an asset gives no per-state moments.
+    crisis_state: usize,
+}
+
+fn build_asset(
+    input: &RetirementInput,
+    asset: &AssetInput,
+    total_weight: f64,
+    regime_model: &RegimeModel,
+) -> Asset {
+    let class = asset_class(&asset.id);
+    let (default_mean, default_std) = match class {
+        AssetClass::Equity => EQUITY_MOMENTS,
+        AssetClass::Bond => BOND_MOMENTS,
+        AssetClass::Cash => CASH_MOMENTS,
+        AssetClass::Other => OTHER_MOMENTS,
+    };
+    let mean = asset.mean_return.unwrap_or(default_mean);
+    let std = asset.return_variability.unwrap_or(default_std).max(0.0);
+
+    // Non-finite months stay in place (as NaN) so the histories stay aligned across assets;
+    // the portfolio drops them for every asset at once.
+    let raw_history: Vec<f64> = asset
+        .historical_monthly_returns
+        .iter()
+        .flatten()
+        .map(|&value| {
+            if value.is_finite() {
+                clamp_monthly_return(value)
+            } else {
+                f64::NAN
+            }
+        })
+        .collect();
+    let finite_history: Vec<f64> = raw_history
+        .iter()
+        .copied()
+        .filter(|value| value.is_finite())
+        .collect();
+    let targets_moments = input.historical_moment_targeting.unwrap_or(false)
+        && asset.mean_return.is_some()
+        && asset.return_variability.is_some();
+    let history = if targets_moments && !finite_history.is_empty() {
+        let n = finite_history.len() as f64;
+        let history_mean = finite_history.iter().sum::<f64>() / n;
+        let history_std = (finite_history
+            .iter()
+            .map(|value| (value - history_mean).powi(2))
+            .sum::<f64>()
+            / n)
+            .sqrt();
+        raw_history
+            .iter()
+            .map(|&value| {
+                if !value.is_finite() {
+                    return value;
+                }
+                clamp_monthly_return(apply_moment_targeting(
+                    value,
+                    history_mean,
+                    history_std,
+                    mean / 12.0,
+                    std / 12.0_f64.sqrt(),
+                ))
+            })
+            .collect()
+    } else {
+        raw_history
+    };
+
+    Asset {
+        id: asset.id.clone(),
+        weight: asset.weight.max(0.0) / total_weight,
+        class,
+        mean,
+        std,
+        skewness: asset.return_skewness.unwrap_or(0.0),
+        kurtosis: asset.return_kurtosis.unwrap_or(3.0),
+        crisis_mean: asset.crisis_mean_return.unwrap_or(mean),
+        crisis_std: asset.crisis_return_variability.unwrap_or(std).max(0.0),
+        regime_means: regime_model
+            .in_model_order(asset.regime_mean_returns.as_deref().unwrap_or_default()),
+        regime_stds: regime_model
+            .in_model_order(
+                asset
+                    .regime_return_variabilities
+                    .as_deref()
+                    .unwrap_or_default(),
+            )
+            .into_iter()
+            .map(|value| value.map(|std| std.max(0.0)))
+            .collect(),
+        history,
+    }
+}
+
+/// Cholesky factor of a symmetric correlation matrix. Pivots that are not positive (the
+/// matrix is not positive definite) are floored so the factor stays usable.
+fn cholesky(matrix: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = matrix.len();
+    let mut lower = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            if i == j {
+                lower[i][j] = (matrix[i][i] - sum).max(1e-9).sqrt();
+            } else {
+                lower[i][j] = (matrix[i][j] - sum) / lower[j][j];
+            }
+        }
+    }
+    lower
+}
+
+impl Portfolio {
+    pub fn from_input(input: &RetirementInput) -> Option<Self> {
+        let portfolio = input.portfolio.as_ref()?;
+        let total_weight: f64 = portfolio
+            .assets
+            .iter()
+            .map(|asset| asset.weight.max(0.0))
+            .sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+        let regime_model = RegimeModel::from_input(&input.regime_model);
+        let mut assets: Vec<Asset> = portfolio
+            .assets
+            .iter()
+            .map(|asset| build_asset(input, asset, total_weight, &regime_model))
+            .collect();
+
+        let n = assets.len();
+        let equity_bond_correlation = input.equity_bond_correlation.clamp(-1.0, 1.0);
+        let correlations: Vec<Vec<f64>> = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        if i == j {
+                            return 1.0;
+                        }
+                        if let Some(value) = portfolio
+                            .correlations
+                            .as_ref()
+                            .and_then(|matrix| matrix.get(i))
+                            .and_then(|row| row.get(j))
+                        {
+                            return value.clamp(-1.0, 1.0);
+                        }
+                        match (assets[i].class, assets[j].class) {
+                            (AssetClass::Equity, AssetClass::Bond)
+                            | (AssetClass::Bond, AssetClass::Equity) => equity_bond_correlation,
+                            _ => 0.0,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Histories are aligned from their first month; all assets need one to bootstrap. A
+        // month is kept only when every asset has a finite return for it.
+        let aligned_len = assets
+            .iter()
+            .map(|asset| asset.history.len())
+            .min()
+            .unwrap_or(0);
+        let mut history_months: Vec<usize> = (0..aligned_len)
+            .filter(|&month| assets.iter().all(|asset| asset.history[month].is_finite()))
+            .collect();
+        if history_months.len() < MIN_HISTORY_MONTHS {
+            history_months.clear();
+        }
+        for asset in &mut assets {
+            asset.history = history_months
+                .iter()
+                .map(|&month| asset.history[month])
+                .collect();
+        }
+
+        Some(Self {
+            cholesky: cholesky(&correlations),
+            crisis_state: regime_model.crisis_state(),
+            history_len: history_months.len(),
+            history_months,
+            assets,
+        })
+    }
+
+    pub fn has_history(&self) -> bool {
+        self.history_len > 0
+    }
+
+    pub fn target_weights(&self) -> Vec<f64> {
+        self.assets.iter().map(|asset| asset.weight).collect()
+    }
+
+    /// Share of the target weights held in equity assets.
+    pub fn equity_weight(&self) -> f64 {
+        self.assets
+            .iter()
+            .filter(|asset| asset.class == AssetClass::Equity)
+            .map(|asset| asset.weight)
+            .sum()
+    }
+
+    /// (equity, bond, cash) shares of a set of weights; assets of other classes count as equity.
+    pub fn class_mix(&self, weights: &[f64]) -> [f64; 3] {
+        let mut mix = [0.0; 3];
+        for (asset, weight) in self.assets.iter().zip(weights) {
+            let slot = match asset.class {
+                AssetClass::Equity | AssetClass::Other => 0,
+                AssetClass::Bond => 1,
+                AssetClass::Cash => 2,
+            };
+            mix[slot] += weight;
+        }
+        mix
+    }
+
+    /// Target weights for each month. A glide path sets the total equity weight by age; equity
+    /// and non-equity assets keep their relative target weights within each side. Without a
+    /// glide path, or without assets on both sides, the static targets apply throughout.
+    pub fn targets_by_month(
+        &self,
+        glide_path: Option<&GlidePath>,
+        current_age: f64,
+        months: usize,
+    ) -> Vec<Vec<f64>> {
+        let targets = self.target_weights();
+        let equity = self.equity_weight();
+        let has_both_sides = self
+            .assets
+            .iter()
+            .any(|asset| asset.class == AssetClass::Equity)
+            && self
+                .assets
+                .iter()
+                .any(|asset| asset.class != AssetClass::Equity);
+        let Some(glide_path) = glide_path.filter(|_| has_both_sides) else {
+            return vec![targets; months];
+        };
+
+        let side_shares: Vec<f64> = self
+            .assets
+            .iter()
+            .map(|asset| {
+                let is_equity = asset.class == AssetClass::Equity;
+                let (side_weight, side_count) = if is_equity {
+                    (equity, self.count_class(true))
+                } else {
+                    (1.0 - equity, self.count_class(false))
+                };
+                if side_weight > 0.0 {
+                    asset.weight / side_weight
+                } else {
+                    1.0 / side_count as f64
+                }
+            })
+            .collect();
+
+        (0..months)
+            .map(|month| {
+                let equity_weight = glide_path.equity_weight(current_age + month as f64 / 12.0);
+                self.assets
+                    .iter()
+                    .zip(&side_shares)
+                    .map(|(asset, share)| {
+                        if asset.class == AssetClass::Equity {
+                            equity_weight * share
+                        } else {
+                            (1.0 - equity_weight) * share
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn count_class(&self, equity: bool) -> usize {
+        self.assets
+            .iter()
+            .filter(|asset| (asset.class == AssetClass::Equity) == equity)
+            .count()
+    }
+
+    /// Position of each history month in the input series, which months with a non-finite
+    /// return on any asset are missing from.
+    pub fn history_months(&self) -> &[usize] {
+        &self.history_months
+    }
+
+    /// Target-weighted monthly history, used to detect regimes and calibrate the bootstrap.
+    pub fn blended_history(&self) -> Vec<f64> {
+        (0..self.history_len)
+            .map(|month| {
+                self.assets
+                    .iter()
+                    .map(|asset| asset.weight * asset.history[month])
+                    .sum()
+            })
+            .collect()
+    }
+
+    pub fn historical_returns(&self, month: usize, returns: &mut [f64]) {
+        for (value, asset) in returns.iter_mut().zip(&self.assets) {
+            *value = asset.history[month % self.history_len];
+        }
+    }
+
+    /// Correlated parametric returns for one month. Each asset uses its `regimeMeanReturns` /
+    /// `regimeReturnVariabilities` entry for the state when given, its crisis moments in the
+    /// crisis state and its normal moments otherwise.
+    pub fn draw_returns(&self, regime_state: usize, rng: &mut RandomSource, returns: &mut [f64]) {
+        let independent: Vec<f64> = self.assets.iter().map(|_| rng.normal(0.0, 1.0)).collect();
+        for (i, (value, asset)) in returns.iter_mut().zip(&self.assets).enumerate() {
+            let z: f64 = (0..=i).map(|k| self.cholesky[i][k] * independent[k]).sum();
+            let (default_mean, default_std) =
+                if regime_state > 0 && regime_state == self.crisis_state {
+                    (asset.crisis_mean, asset.crisis_std)
+                } else {
+                    (asset.mean, asset.std)
+                };
+            let mean = asset
+                .regime_means
+                .get(regime_state)
+                .copied()
+                .flatten()
+                .unwrap_or(default_mean);
+            let std = asset
+                .regime_stds
+                .get(regime_state)
+                .copied()
+                .flatten()
+                .unwrap_or(default_std);
+            *value = clamp_monthly_return(
+                mean / 12.0
+                    + std / 12.0_f64.sqrt()
+                        * cornish_fisher_score(z, asset.skewness, asset.kurtosis),
+            );
+        }
+    }
+}
+
+/// Equity weight by age, interpolated linearly between points and flat beyond them.
+pub struct GlidePath {
+    pub name: String,
+    points: Vec<(f64, f64)>,
+}
+
+impl GlidePath {
+    fn from_params(
+        name: &str,
+        retirement_age: f64,
+        (start, at_retirement, end, years_before, years_after): (f64, f64, f64, f64, f64),
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            points: vec![
+                (
+                    retirement_age - years_before.max(0.0),
+                    start.clamp(0.0, 1.0),
+                ),
+                (retirement_age, at_retirement.clamp(0.0, 1.0)),
+                (retirement_age + years_after.max(0.0), end.clamp(0.0, 1.0)),
+            ],
+        }
+    }
+
+    /// Explicit `points` if given, otherwise the target-date parameters: `startEquity` until
+    /// `yearsBeforeRetirement`, a straight line to `retirementEquity` at retirement and another
+    /// to `endEquity` over `yearsAfterRetirement`. An end above the retirement weight is a
+    /// rising-equity bond tent.
+    pub fn from_input(input: &GlidePathInput, retirement_age: f64, static_equity: f64) -> Self {
+        let name = input.name.as_deref().unwrap_or("configured");
+        let mut points: Vec<(f64, f64)> = input
+            .points
+            .iter()
+            .flatten()
+            .filter(|point| point.age.is_finite() && point.equity_weight.is_finite())
+            .map(|point| (point.age, point.equity_weight.clamp(0.0, 1.0)))
+            .collect();
+        if !points.is_empty() {
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            return Self {
+                name: name.to_string(),
+                points,
+            };
+        }
+
+        let start = input.start_equity.unwrap_or(static_equity);
+        let at_retirement = input.retirement_equity.unwrap_or(start);
+        let end = input.end_equity.unwrap_or(at_retirement);
+        Self::from_params(
+            name,
+            retirement_age,
+            (
+                start,
+                at_retirement,
+                end,
+                input
+                    .years_before_retirement
+                    .unwrap_or(DEFAULT_GLIDE_YEARS_BEFORE),
+                input
+                    .years_after_retirement
+                    .unwrap_or(DEFAULT_GLIDE_YEARS_AFTER),
+            ),
+        )
+    }
+
+    /// The glide paths compared when none are listed: static weights, a target-date fund,
+    /// a bond tent around the static equity weight and the configured path, if any.
+    pub fn defaults(retirement_age: f64, static_equity: f64) -> Vec<Self> {
+        vec![
+            Self {
+                name: "static".to_string(),
+                points: vec![(retirement_age, static_equity)],
+            },
+            Self::from_params("targetDate", retirement_age, TARGET_DATE_GLIDE),
+            Self::from_params(
+                "bondTent",
+                retirement_age,
+                (
+                    static_equity,
+                    static_equity / 2.0,
+                    static_equity,
+                    BOND_TENT_YEARS.0,
+                    BOND_TENT_YEARS.1,
+                ),
+            ),
+        ]
+    }
+
+    pub fn equity_weight(&self, age: f64) -> f64 {
+        let (Some(&first), Some(&last)) = (self.points.first(), self.points.last()) else {
+            return 0.0;
+        };
+        if age <= first.0 {
+            return first.1;
+        }
+        if age >= last.0 {
+            return last.1;
+        }
+        let upper = self
+            .points
+            .partition_point(|&(point_age, _)| point_age <= age);
+        let (age_a, weight_a) = self.points[upper - 1];
+        let (age_b, weight_b) = self.points[upper];
+        weight_a + (weight_b - weight_a) * (age - age_a) / (age_b - age_a)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum RebalancePolicy {
+    Never,
+    // Back to target every given number of months.
+    Calendar(usize),
+    // Back to target once any weight is further than the band from its target.
+    Tolerance(f64),
+    // Contributions buy underweight assets and withdrawals sell overweight ones; no trades.
+    CashFlow,
+}
+
+impl RebalancePolicy {
+    /// Rejects a `mode` none of the policies handles, rather than rebalancing monthly.
+    pub fn from_input(input: Option<&RebalancingInput>) -> Result<Self, String> {
+        let band = Self::tolerance_band(input);
+        match input.and_then(|rebalancing| rebalancing.mode.as_deref()) {
+            None | Some("monthly") => Ok(RebalancePolicy::Calendar(1)),
+            Some("never") => Ok(RebalancePolicy::Never),
+            Some("quarterly") => Ok(RebalancePolicy::Calendar(3)),
+            Some("annual") => Ok(RebalancePolicy::Calendar(12)),
+            Some("tolerance") => Ok(RebalancePolicy::Tolerance(band)),
+            Some("cashFlow") => Ok(RebalancePolicy::CashFlow),
+            Some(mode) => Err(format!(
+                "unknown rebalancing mode '{}'; expected 'monthly', 'quarterly', 'annual', \
+                 'never', 'tolerance' or 'cashFlow'",
+                mode
+            )),
+        }
+    }
+
+    fn tolerance_band(input: Option<&RebalancingInput>) -> f64 {
+        input
+            .and_then(|rebalancing| rebalancing.tolerance_band)
+            .unwrap_or(DEFAULT_TOLERANCE_BAND)
+            .max(0.0)
+    }
+
+    /// Every policy, with the tolerance band taken from the input, for side-by-side comparison.
+    pub fn all(input: Option<&RebalancingInput>) -> Vec<Self> {
+        vec![
+            RebalancePolicy::Never,
+            RebalancePolicy::Calendar(1),
+            RebalancePolicy::Calendar(3),
+            RebalancePolicy::Calendar(12),
+            RebalancePolicy::Tolerance(Self::tolerance_band(input)),
+            RebalancePolicy::CashFlow,
+        ]
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            RebalancePolicy::Never => "never".to_string(),
+            RebalancePolicy::Calendar(1) => "monthly".to_string(),
+            RebalancePolicy::Calendar(3) => "quarterly".to_string(),
+            RebalancePolicy::Calendar(12) => "annual".to_string(),
+            RebalancePolicy::Calendar(months) => format!("every{}Months", months),
+            RebalancePolicy::Tolerance(_) => "tolerance".to_string(),
+            RebalancePolicy::CashFlow => "cashFlow".to_string(),
+        }
+    }
+}
+
+/// Current asset weights of one path as they drift with returns and cash flows.
+pub struct PortfolioState {
+    pub weights: Vec<f64>,
+}
+
+impl PortfolioState {
+    pub fn new(targets: &[f64]) -> Self {
+        Self {
+            weights: targets.to_vec(),
+        }
+    }
+
+    /// Applies one month's asset returns and returns the portfolio return.
+    pub fn grow(&mut self, returns: &[f64]) -> f64 {
+        let portfolio_return: f64 = self
+            .weights
+            .iter()
+            .zip(returns)
+            .map(|(weight, value)| weight * value)
+            .sum();
+        if portfolio_return > -1.0 {
+            for (weight, value) in self.weights.iter_mut().zip(returns) {
+                *weight *= (1.0 + value) / (1.0 + portfolio_return);
+            }
+        }
+        portfolio_return
+    }
+
+    /// Adds `flow` to a portfolio worth `balance`. Under the cash-flow policy the flow goes to
+    /// the assets furthest from target; otherwise it is spread pro rata.
+    pub fn apply_flow(
+        &mut self,
+        policy: RebalancePolicy,
+        targets: &[f64],
+        balance: f64,
+        flow: f64,
+    ) {
+        let new_total = balance + flow;
+        if !matches!(policy, RebalancePolicy::CashFlow) || balance <= 0.0 || new_total <= 0.0 {
+            return;
+        }
+        let mut holdings: Vec<f64> = self.weights.iter().map(|weight| weight * balance).collect();
+        // Gaps toward target in the direction of the flow.
+        let gaps: Vec<f64> = holdings
+            .iter()
+            .zip(targets)
+            .map(|(holding, target)| (flow.signum() * (target * new_total - holding)).max(0.0))
+            .collect();
+        let total_gap: f64 = gaps.iter().sum();
+        let amount = flow.abs();
+        for ((holding, gap), target) in holdings.iter_mut().zip(&gaps).zip(targets) {
+            let trade = if total_gap >= amount {
+                amount * gap / total_gap
+            } else {
+                gap + (amount - total_gap) * target
+            };
+            *holding = (*holding + flow.signum() * trade).max(0.0);
+        }
+        let total: f64 = holdings.iter().sum();
+        if total > 0.0 {
+            for (weight, holding) in self.weights.iter_mut().zip(holdings) {
+                *weight = holding / total;
+            }
+        }
+    }
+
+    /// Rebalances at the end of `month` if the policy calls for it and returns the share of
+    /// the portfolio sold.
+    pub fn rebalance(&mut self, policy: RebalancePolicy, targets: &[f64], month: usize) -> f64 {
+        let due = match policy {
+            RebalancePolicy::Calendar(period) => (month + 1).is_multiple_of(period.max(1)),
+            RebalancePolicy::Tolerance(band) => self
+                .weights
+                .iter()
+                .zip(targets)
+                .any(|(weight, target)| (weight - target).abs() > band),
+            RebalancePolicy::Never | RebalancePolicy::CashFlow => false,
+        };
+        if !due {
+            return 0.0;
+        }
+        let sold = self
+            .weights
+            .iter()
+            .zip(targets)
+            .map(|(weight, target)| (weight - target).max(0.0))
+            .sum();
+        self.weights.copy_from_slice(targets);
+        sold
+    }
+}
+
+/// A rebalancing policy and monthly targets, replayed on each path's per-asset returns as the
+/// main loop draws them, so the returns never need to be stored.
+pub struct AllocationCandidate {
+    policy: RebalancePolicy,
+    targets_by_month: Vec<Vec<f64>>,
+    success_count: usize,
+    terminal_balances: Vec<f64>,
+    // Mean share of the portfolio sold per year, per path.
+    annual_turnover: Vec<f64>,
+}
+
+impl AllocationCandidate {
+    pub fn new(policy: RebalancePolicy, targets_by_month: Vec<Vec<f64>>) -> Self {
+        Self {
+            policy,
+            targets_by_month,
+            success_count: 0,
+            terminal_balances: Vec::new(),
+            annual_turnover: Vec::new(),
+        }
+    }
+
+    /// Replays one path through the month step under this candidate's allocation.
+    pub fn replay_path(&mut self, replay: &AllocationReplay, path: &AllocationPath) {
+        let outcome = replay.run_path(path, self.policy, &self.targets_by_month);
+        if outcome.succeeded {
+            self.success_count += 1;
+        }
+        self.terminal_balances.push(outcome.terminal_balance);
+        self.annual_turnover
+            .push(outcome.sold / (path.inflation.len() as f64 / 12.0).max(1.0 / 12.0));
+    }
+
+    fn success_probability(&self) -> f64 {
+        self.success_count as f64 / self.terminal_balances.len().max(1) as f64
+    }
+}
+
+/// Success rate, median terminal balance and median turnover of every rebalancing policy on
+/// the same return paths.
+pub fn compare_rebalancing_policies(candidates: &[AllocationCandidate]) -> Vec<RebalancingOutcome> {
+    candidates
+        .iter()
+        .map(|candidate| RebalancingOutcome {
+            policy: candidate.policy.label(),
+            success_probability: candidate.success_probability(),
+            median_terminal_balance: summarize(&candidate.terminal_balances).p50,
+            median_annual_turnover: summarize(&candidate.annual_turnover).p50,
+        })
+        .collect()
+}
+
+/// Success rate and terminal balances of each glide path, replayed under the same rebalancing
+/// policy on the same return paths.
+pub fn compare_glide_paths(
+    candidates: &[(GlidePath, AllocationCandidate)],
+    retirement_age: f64,
+    end_age: f64,
+) -> Vec<GlidePathOutcome> {
+    candidates
+        .iter()
+        .map(|(glide_path, candidate)| {
+            let terminal = summarize(&candidate.terminal_balances);
+            GlidePathOutcome {
+                name: glide_path.name.clone(),
+                retirement_equity: glide_path.equity_weight(retirement_age),
+                end_equity: glide_path.equity_weight(end_age),
+                success_probability: candidate.success_probability(),
+                p10_terminal_balance: terminal.p10,
+                median_terminal_balance: terminal.p50,
+            }
+        })
+        .collect()
+}
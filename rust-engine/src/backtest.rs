@@ -0,0 +1,67 @@
+use crate::engine::HistoricalCohort;
+
+/// Start months replayed by the sequential backtest. Only cohorts whose whole horizon fits in
+/// the history are used; a history shorter than the horizon starts a cohort in every month and
+/// wraps back to its beginning.
+pub fn cohort_count(history_len: usize, months: usize) -> usize {
+    if history_len >= months {
+        history_len - months + 1
+    } else {
+        history_len
+    }
+}
+
+/// "YYYY-MM" label `offset` months after `start`.
+pub fn month_label(start: &str, offset: usize) -> Option<String> {
+    let (year, month) = start.trim().split_once('-')?;
+    let year: i64 = year.parse().ok()?;
+    let month: i64 = month.get(..2).unwrap_or(month).parse().ok()?;
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    let index = year * 12 + month - 1 + offset as i64;
+    Some(format!(
+        "{:04}-{:02}",
+        index.div_euclid(12),
+        index.rem_euclid(12) + 1
+    ))
+}
+
+/// Summarizes one replayed cohort from its month-end real balances.
+pub fn build_cohort(
+    start_index: usize,
+    start_month: Option<String>,
+    wrapped: bool,
+    balances: &[f64],
+    success: bool,
+    current_age: f64,
+    retire_month: usize,
+) -> HistoricalCohort {
+    let mut peak = 0.0_f64;
+    let mut worst_drawdown = 0.0_f64;
+    for &balance in balances {
+        peak = peak.max(balance);
+        if peak > 0.0 {
+            worst_drawdown = worst_drawdown.max((peak - balance) / peak);
+        }
+    }
+
+    let retire_index = retire_month.min(balances.len().saturating_sub(1));
+    let (min_month, min_balance) = balances
+        .iter()
+        .enumerate()
+        .skip(retire_index)
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map_or((retire_index, 0.0), |(month, &balance)| (month, balance));
+
+    HistoricalCohort {
+        start_index,
+        start_month,
+        wrapped,
+        success,
+        ending_balance: balances.last().copied().unwrap_or(0.0),
+        worst_drawdown,
+        min_balance,
+        min_balance_age: current_age + min_month as f64 / 12.0,
+    }
+}
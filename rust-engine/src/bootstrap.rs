@@ -0,0 +1,131 @@
+use crate::calculations::RandomSource;
+
+/// How monthly history is resampled, one per historical `simulation_mode`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BootstrapScheme {
+    // Fixed-length blocks started in the current regime's pool; a regime change starts a new block.
+    RegimeBlock,
+    // Fixed-length blocks from anywhere in the history, never running past its end.
+    Block,
+    // Fixed-length blocks that wrap from the end of the history back to its start.
+    Circular,
+    // Politis-Romano: geometric block lengths with mean `block_length`, wrapping.
+    Stationary,
+    // Every month drawn independently.
+    Iid,
+    // No resampling: each path replays the history in order from its own start month.
+    Sequential,
+}
+
+impl BootstrapScheme {
+    /// `None` for the parametric mode.
+    pub fn from_mode(mode: &str) -> Option<Self> {
+        match mode {
+            "historical" => Some(BootstrapScheme::RegimeBlock),
+            "blockBootstrap" => Some(BootstrapScheme::Block),
+            "circularBootstrap" => Some(BootstrapScheme::Circular),
+            "stationaryBootstrap" => Some(BootstrapScheme::Stationary),
+            "iidBootstrap" => Some(BootstrapScheme::Iid),
+            "historicalSequential" => Some(BootstrapScheme::Sequential),
+            _ => None,
+        }
+    }
+
+    pub fn conditions_on_regime(&self) -> bool {
+        *self == BootstrapScheme::RegimeBlock
+    }
+}
+
+fn uniform_index(len: usize, rng: &mut RandomSource) -> usize {
+    ((rng.random() * len as f64).floor() as usize).min(len.saturating_sub(1))
+}
+
+/// Walks one path through the monthly history under a bootstrap scheme.
+pub struct BlockSampler {
+    scheme: BootstrapScheme,
+    block_length: usize,
+    index: usize,
+    remaining: usize,
+    start: usize,
+}
+
+impl BlockSampler {
+    pub fn new(scheme: BootstrapScheme, block_length: usize) -> Self {
+        Self {
+            scheme,
+            block_length: block_length.max(1),
+            index: 0,
+            remaining: 0,
+            start: 0,
+        }
+    }
+
+    /// First history index of a sequential path.
+    pub fn starting_at(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// History index for the next month. `regime_pool` holds the block starts for the current
+    /// regime and is only used by the regime-conditioned scheme.
+    pub fn next(
+        &mut self,
+        history_len: usize,
+        regime_pool: &[usize],
+        regime_changed: bool,
+        rng: &mut RandomSource,
+    ) -> usize {
+        match self.scheme {
+            BootstrapScheme::RegimeBlock => {
+                if self.remaining == 0 || regime_changed {
+                    let random_idx = (rng.random() * regime_pool.len() as f64).floor() as usize;
+                    self.index = regime_pool[random_idx.min(regime_pool.len().saturating_sub(1))];
+                    self.remaining = self.block_length;
+                } else {
+                    self.index = (self.index + 1) % history_len;
+                }
+                self.remaining -= 1;
+            }
+            BootstrapScheme::Block => {
+                if self.remaining == 0 {
+                    let block_length = self.block_length.min(history_len);
+                    self.index = uniform_index(history_len - block_length + 1, rng);
+                    self.remaining = block_length;
+                } else {
+                    self.index += 1;
+                }
+                self.remaining -= 1;
+            }
+            BootstrapScheme::Circular => {
+                if self.remaining == 0 {
+                    self.index = uniform_index(history_len, rng);
+                    self.remaining = self.block_length;
+                } else {
+                    self.index = (self.index + 1) % history_len;
+                }
+                self.remaining -= 1;
+            }
+            BootstrapScheme::Stationary => {
+                // `remaining` only marks the first month of the path here.
+                if self.remaining == 0 || rng.random() < 1.0 / self.block_length as f64 {
+                    self.index = uniform_index(history_len, rng);
+                    self.remaining = 1;
+                } else {
+                    self.index = (self.index + 1) % history_len;
+                }
+            }
+            BootstrapScheme::Iid => {
+                self.index = uniform_index(history_len, rng);
+            }
+            BootstrapScheme::Sequential => {
+                if self.remaining == 0 {
+                    self.index = self.start % history_len;
+                    self.remaining = 1;
+                } else {
+                    self.index = (self.index + 1) % history_len;
+                }
+            }
+        }
+        self.index
+    }
+}
@@ -37,10 +37,6 @@ impl RandomSource {
         if let Some(state) = &mut self.state {
             // Mulberry32
             *state = state.wrapping_add(0x6D2B79F5);
-            let mut t = *state ^ (*state >> 15);
-            t = t.wrapping_mul(1 | *state);
-            t ^= t.wrapping_add(t.wrapping_mul(t ^ (t >> 7)) & (61 | t)); // Approximation of JS Math.imul
-
             // Following exact JS imul steps:
             let js_imul1 = (*state ^ (*state >> 15)).wrapping_mul(1 | *state);
             let js_imul2 = (js_imul1 ^ (js_imul1 >> 7)).wrapping_mul(61 | js_imul1);
@@ -117,6 +113,37 @@ pub fn summarize(values: &[f64]) -> PercentileSeries<f64> {
     }
 }
 
+/// Percentiles across paths at each index. NaN marks an index a path has no value for (a
+/// depleted path holds no weights, for example) and is left out of that index.
+pub fn percentile_bands(paths: &[Vec<f64>], len: usize) -> PercentileSeries<Vec<f64>> {
+    let mut bands = PercentileSeries {
+        p10: Vec::with_capacity(len),
+        p25: Vec::with_capacity(len),
+        p50: Vec::with_capacity(len),
+        p75: Vec::with_capacity(len),
+        p90: Vec::with_capacity(len),
+    };
+
+    let mut column = Vec::with_capacity(paths.len());
+    for index in 0..len {
+        column.clear();
+        column.extend(
+            paths
+                .iter()
+                .map(|path| path[index])
+                .filter(|value| !value.is_nan()),
+        );
+        column.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        bands.p10.push(percentile(&column, 0.1));
+        bands.p25.push(percentile(&column, 0.25));
+        bands.p50.push(percentile(&column, 0.5));
+        bands.p75.push(percentile(&column, 0.75));
+        bands.p90.push(percentile(&column, 0.9));
+    }
+
+    bands
+}
+
 pub fn clamp(value: f64, min: f64, max: f64) -> f64 {
     if value < min {
         min
@@ -0,0 +1,219 @@
+use crate::calculations::summarize;
+use crate::engine::{ClaimingAgeOutcome, ClaimingAnalysis};
+use crate::replay::{PathReplay, PlanInputs, ReplayOptions};
+use crate::structs::ClaimingInput;
+
+const DEFAULT_FULL_RETIREMENT_AGE: f64 = 67.0;
+const DEFAULT_EARLIEST_AGE: f64 = 62.0;
+const DEFAULT_LATEST_AGE: f64 = 70.0;
+
+// US Social Security: 5/9% per month for the first 36 months claimed early, 5/12% per month
+// beyond that, and 2/3% per month of delayed retirement credits.
+const FIRST_EARLY_MONTHS: f64 = 36.0;
+const FIRST_EARLY_REDUCTION: f64 = 5.0 / 900.0;
+const LATER_EARLY_REDUCTION: f64 = 5.0 / 1200.0;
+const DELAYED_CREDIT: f64 = 2.0 / 300.0;
+
+/// Benefit adjustment for a pension whose `yearly_amount` is the full-retirement-age benefit.
+pub struct ClaimingRule {
+    full_retirement_age: f64,
+    earliest_age: f64,
+    latest_age: f64,
+    // Explicit (age, factor) points, sorted by age; empty means the US formula.
+    factors: Vec<(f64, f64)>,
+}
+
+impl ClaimingRule {
+    pub fn from_input(input: &ClaimingInput) -> Self {
+        let full_retirement_age = input
+            .full_retirement_age
+            .unwrap_or(DEFAULT_FULL_RETIREMENT_AGE);
+        let earliest_age = input
+            .earliest_age
+            .unwrap_or(DEFAULT_EARLIEST_AGE)
+            .min(full_retirement_age);
+        let latest_age = input
+            .latest_age
+            .unwrap_or(DEFAULT_LATEST_AGE)
+            .max(full_retirement_age);
+
+        let mut factors: Vec<(f64, f64)> = input
+            .adjustment_factors
+            .iter()
+            .flatten()
+            .filter(|point| point.age.is_finite() && point.factor.is_finite())
+            .map(|point| (point.age, point.factor.max(0.0)))
+            .collect();
+        factors.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self {
+            full_retirement_age,
+            earliest_age,
+            latest_age,
+            factors,
+        }
+    }
+
+    pub fn claiming_age(&self, input: &ClaimingInput) -> f64 {
+        input
+            .claiming_age
+            .unwrap_or(self.full_retirement_age)
+            .clamp(self.earliest_age, self.latest_age)
+    }
+
+    /// Whole-year claiming ages from the earliest to the latest allowed.
+    pub fn candidate_ages(&self) -> Vec<f64> {
+        let years = (self.latest_age - self.earliest_age).floor() as usize;
+        (0..=years)
+            .map(|year| self.earliest_age + year as f64)
+            .collect()
+    }
+
+    pub fn benefit_factor(&self, claiming_age: f64) -> f64 {
+        let age = claiming_age.clamp(self.earliest_age, self.latest_age);
+        if let (Some(&first), Some(&last)) = (self.factors.first(), self.factors.last()) {
+            if age <= first.0 {
+                return first.1;
+            }
+            if age >= last.0 {
+                return last.1;
+            }
+            let upper = self
+                .factors
+                .partition_point(|&(point_age, _)| point_age <= age);
+            let (age_a, factor_a) = self.factors[upper - 1];
+            let (age_b, factor_b) = self.factors[upper];
+            return factor_a + (factor_b - factor_a) * (age - age_a) / (age_b - age_a);
+        }
+
+        let months_from_full = (age - self.full_retirement_age) * 12.0;
+        if months_from_full >= 0.0 {
+            1.0 + DELAYED_CREDIT * months_from_full
+        } else {
+            let months_early = -months_from_full;
+            1.0 - FIRST_EARLY_REDUCTION * months_early.min(FIRST_EARLY_MONTHS)
+                - LATER_EARLY_REDUCTION * (months_early - FIRST_EARLY_MONTHS).max(0.0)
+        }
+    }
+}
+
+/// Replays every allowed claiming age of the first income source with a `claiming` rule
+/// through the simulation's month step over its stored paths and each path's death months. A
+/// path succeeds under the headline success definition; the bequest is the balance at death
+/// (or at the horizon).
+pub fn analyze_claiming_ages(replay: &PathReplay) -> Option<ClaimingAnalysis> {
+    let plan = replay.plan;
+    let income_sources = plan.income_sources;
+    let source_index = income_sources
+        .iter()
+        .position(|source| source.claiming.is_some())?;
+    let claiming = income_sources[source_index].claiming.as_ref()?;
+    let rule = ClaimingRule::from_input(claiming);
+    let path_count = replay.path_count();
+    if path_count == 0 {
+        return None;
+    }
+
+    let outcomes: Vec<ClaimingAgeOutcome> = rule
+        .candidate_ages()
+        .into_iter()
+        .map(|claiming_age| {
+            let mut sources = income_sources.to_vec();
+            if let Some(claiming) = sources[source_index].claiming.as_mut() {
+                claiming.claiming_age = Some(claiming_age);
+            }
+            let flows = replay.flows(&PlanInputs {
+                income_sources: &sources,
+                ..plan
+            });
+
+            let mut success_count = 0;
+            let mut bequests = Vec::with_capacity(path_count);
+            for sim in 0..path_count {
+                let outcome = replay.run_path(
+                    sim,
+                    flows.path(sim),
+                    &replay.rules,
+                    &ReplayOptions::default(),
+                );
+                if replay.succeeded(sim, &outcome) {
+                    success_count += 1;
+                }
+                bequests.push(outcome.death_balance);
+            }
+
+            ClaimingAgeOutcome {
+                claiming_age,
+                annual_benefit: income_sources[source_index].yearly_amount
+                    * rule.benefit_factor(claiming_age),
+                success_probability: success_count as f64 / path_count as f64,
+                median_bequest: summarize(&bequests).p50,
+            }
+        })
+        .collect();
+
+    let best_claiming_age = outcomes
+        .iter()
+        .max_by(|a, b| {
+            a.success_probability
+                .total_cmp(&b.success_probability)
+                .then(a.median_bequest.total_cmp(&b.median_bequest))
+        })?
+        .claiming_age;
+
+    Some(ClaimingAnalysis {
+        source_id: income_sources[source_index].id.clone(),
+        outcomes,
+        best_claiming_age,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::ClaimingFactorInput;
+
+    fn claiming_input(factors: Option<Vec<(f64, f64)>>) -> ClaimingInput {
+        ClaimingInput {
+            claiming_age: None,
+            full_retirement_age: None,
+            earliest_age: None,
+            latest_age: None,
+            adjustment_factors: factors.map(|points| {
+                points
+                    .into_iter()
+                    .map(|(age, factor)| ClaimingFactorInput { age, factor })
+                    .collect()
+            }),
+        }
+    }
+
+    #[test]
+    fn benefit_factor_follows_the_us_formula() {
+        let rule = ClaimingRule::from_input(&claiming_input(None));
+        // 36 months at 5/9% and 24 at 5/12% early; 36 months of 2/3% delayed credits.
+        assert!((rule.benefit_factor(62.0) - 0.70).abs() < 1e-12);
+        assert!((rule.benefit_factor(67.0) - 1.0).abs() < 1e-12);
+        assert!((rule.benefit_factor(70.0) - 1.24).abs() < 1e-12);
+        // Ages outside the claiming window are clamped to it.
+        assert!((rule.benefit_factor(75.0) - 1.24).abs() < 1e-12);
+        assert_eq!(
+            rule.candidate_ages(),
+            [62.0, 63.0, 64.0, 65.0, 66.0, 67.0, 68.0, 69.0, 70.0]
+        );
+    }
+
+    #[test]
+    fn benefit_factor_interpolates_explicit_factors() {
+        let input = ClaimingInput {
+            full_retirement_age: Some(65.0),
+            earliest_age: Some(60.0),
+            ..claiming_input(Some(vec![(70.0, 1.3), (60.0, 0.8), (65.0, 1.0)]))
+        };
+        let rule = ClaimingRule::from_input(&input);
+        assert!((rule.benefit_factor(60.0) - 0.8).abs() < 1e-12);
+        assert!((rule.benefit_factor(62.5) - 0.9).abs() < 1e-12);
+        assert!((rule.benefit_factor(67.0) - 1.12).abs() < 1e-12);
+        assert!((rule.benefit_factor(70.0) - 1.3).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::calculations::{percentile, RandomSource};
+use crate::engine::{ConfidenceInterval, ConvergenceStats, FI_TARGET_SUCCESS_PROBABILITY};
+use crate::stats::find_retirement_balance_target;
+use crate::structs::ConvergenceInput;
+
+// Two-sided 95% normal quantile.
+const CONFIDENCE_Z: f64 = 1.96;
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_MAX_SIMULATIONS: usize = 20000;
+// Resampling draws come from their own stream so the diagnostics leave the paths unchanged.
+pub const BOOTSTRAP_SEED_OFFSET: f64 = 15485863.0;
+
+/// 95% Wilson score interval for a proportion `p` estimated from `n` trials.
+pub fn wilson_interval(p: f64, n: usize) -> ConfidenceInterval {
+    let n = n.max(1) as f64;
+    let z2 = CONFIDENCE_Z * CONFIDENCE_Z;
+    let denominator = 1.0 + z2 / n;
+    let center = (p + z2 / (2.0 * n)) / denominator;
+    let half_width = CONFIDENCE_Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    ConfidenceInterval {
+        lower: (center - half_width).max(0.0),
+        upper: (center + half_width).min(1.0),
+    }
+}
+
+/// Adds paths in batches after the requested count until the Wilson half-width of the reported
+/// success probability is at most `tolerance`, or the path or time budget runs out.
+pub struct AdaptiveSampling {
+    tolerance: f64,
+    min_paths: usize,
+    max_paths: usize,
+    batch_size: usize,
+    // Deadline in milliseconds on `clock`.
+    deadline: Option<f64>,
+    clock: fn() -> f64,
+}
+
+/// Milliseconds since the epoch; the JS clock in the browser, the system clock natively.
+pub fn host_clock() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+    }
+}
+
+impl AdaptiveSampling {
+    /// `clock` returns milliseconds and times the `maxSeconds` budget.
+    pub fn from_input(
+        input: Option<&ConvergenceInput>,
+        min_paths: usize,
+        clock: fn() -> f64,
+    ) -> Option<Self> {
+        let input = input?;
+        let tolerance = input.tolerance.filter(|&tolerance| tolerance > 0.0)?;
+        let max_paths = input
+            .max_simulations
+            .map_or(DEFAULT_MAX_SIMULATIONS, |paths| {
+                paths.max(0.0).round() as usize
+            });
+        Some(Self {
+            tolerance,
+            min_paths,
+            max_paths: max_paths.max(min_paths),
+            batch_size: input
+                .batch_size
+                .map_or(DEFAULT_BATCH_SIZE, |size| size.round() as usize)
+                .max(1),
+            deadline: input
+                .max_seconds
+                .map(|seconds| clock() + seconds.max(0.0) * 1000.0),
+            clock,
+        })
+    }
+
+    pub fn max_paths(&self) -> usize {
+        self.max_paths
+    }
+
+    /// Why sampling stops after `paths` paths with `success_count` successes, checked at the
+    /// end of each batch; `None` to keep going.
+    pub fn stop_reason(&self, paths: usize, success_count: usize) -> Option<&'static str> {
+        let batch_end =
+            paths >= self.min_paths && (paths - self.min_paths).is_multiple_of(self.batch_size);
+        if !batch_end && paths < self.max_paths {
+            return None;
+        }
+        let interval = wilson_interval(success_count as f64 / paths as f64, paths);
+        if (interval.upper - interval.lower) / 2.0 <= self.tolerance {
+            Some("tolerance")
+        } else if paths >= self.max_paths {
+            Some("paths")
+        } else if self
+            .deadline
+            .is_some_and(|deadline| (self.clock)() >= deadline)
+        {
+            Some("time")
+        } else {
+            None
+        }
+    }
+
+    /// Share of the path or time budget used so far.
+    pub fn progress(&self, paths: usize, started: Option<f64>) -> f64 {
+        let by_paths = paths as f64 / self.max_paths as f64;
+        let by_time = self
+            .deadline
+            .zip(started)
+            .map_or(0.0, |(deadline, started)| {
+                ((self.clock)() - started) / (deadline - started).max(1.0)
+            });
+        by_paths.max(by_time).min(1.0)
+    }
+
+    pub fn started(&self) -> Option<f64> {
+        self.deadline.map(|_| (self.clock)())
+    }
+}
+
+// Central 95% of the resampled values.
+fn percentile_interval(mut values: Vec<f64>) -> ConfidenceInterval {
+    values.sort_by(f64::total_cmp);
+    ConfidenceInterval {
+        lower: percentile(&values, 0.025),
+        upper: percentile(&values, 0.975),
+    }
+}
+
+/// Standard error and Wilson interval of the success probability, and path-bootstrap
+/// intervals for the FI target and the key balance percentiles.
+pub fn build_convergence_stats(
+    input: Option<&ConvergenceInput>,
+    seed: Option<f64>,
+    success_probability: f64,
+    retire_balances: &[f64],
+    final_balances: &[f64],
+    stopped_by: Option<&'static str>,
+) -> ConvergenceStats {
+    let path_count = final_balances.len();
+    let resamples = if path_count == 0 {
+        0
+    } else {
+        input
+            .and_then(|input| input.bootstrap_resamples)
+            .map_or(0, |resamples| resamples.max(0.0).round() as usize)
+    };
+
+    let mut rng = RandomSource::new(seed.map(|seed| seed + BOOTSTRAP_SEED_OFFSET));
+    let mut fi_targets = Vec::with_capacity(resamples);
+    let mut final_p10 = Vec::with_capacity(resamples);
+    let mut final_p50 = Vec::with_capacity(resamples);
+    let mut final_p90 = Vec::with_capacity(resamples);
+    let mut retire_p50 = Vec::with_capacity(resamples);
+    let mut resampled_retire = vec![0.0; path_count];
+    let mut resampled_final = vec![0.0; path_count];
+    for _ in 0..resamples {
+        for (retire, terminal) in resampled_retire.iter_mut().zip(&mut resampled_final) {
+            let path = ((rng.random() * path_count as f64) as usize).min(path_count - 1);
+            *retire = retire_balances[path];
+            *terminal = final_balances[path];
+        }
+        fi_targets.push(find_retirement_balance_target(
+            &resampled_retire,
+            &resampled_final,
+            FI_TARGET_SUCCESS_PROBABILITY,
+        ));
+        let mut sorted_final = resampled_final.clone();
+        sorted_final.sort_by(f64::total_cmp);
+        final_p10.push(percentile(&sorted_final, 0.1));
+        final_p50.push(percentile(&sorted_final, 0.5));
+        final_p90.push(percentile(&sorted_final, 0.9));
+        let mut sorted_retire = resampled_retire.clone();
+        sorted_retire.sort_by(f64::total_cmp);
+        retire_p50.push(percentile(&sorted_retire, 0.5));
+    }
+    let bootstrapped = resamples > 0;
+
+    let success_interval = wilson_interval(success_probability, path_count);
+    ConvergenceStats {
+        path_count,
+        success_standard_error: (success_probability * (1.0 - success_probability)
+            / path_count.max(1) as f64)
+            .sqrt(),
+        success_half_width: (success_interval.upper - success_interval.lower) / 2.0,
+        success_interval,
+        bootstrap_resamples: resamples,
+        fi_target: bootstrapped.then(|| percentile_interval(fi_targets)),
+        final_low: bootstrapped.then(|| percentile_interval(final_p10)),
+        final_median: bootstrapped.then(|| percentile_interval(final_p50)),
+        final_high: bootstrapped.then(|| percentile_interval(final_p90)),
+        retire_median: bootstrapped.then(|| percentile_interval(retire_p50)),
+        stopped_by: stopped_by.map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wilson_interval_matches_hand_computed_bounds() {
+        // 50 of 100: center 0.5, half-width 1.96 * sqrt(0.0025 + 1.96² / 40000) / (1 + 1.96² / 100).
+        let interval = wilson_interval(0.5, 100);
+        assert!((interval.lower - 0.403830).abs() < 1e-6);
+        assert!((interval.upper - 0.596170).abs() < 1e-6);
+
+        // 0 of 10 still has an upper bound: 2 * (1.96² / 20) / (1 + 1.96² / 10).
+        let interval = wilson_interval(0.0, 10);
+        assert_eq!(interval.lower, 0.0);
+        assert!((interval.upper - 0.277540).abs() < 1e-6);
+    }
+
+    fn frozen_clock() -> f64 {
+        0.0
+    }
+
+    fn sampling(tolerance: f64, max_paths: f64, max_seconds: Option<f64>) -> AdaptiveSampling {
+        let input = ConvergenceInput {
+            tolerance: Some(tolerance),
+            max_simulations: Some(max_paths),
+            max_seconds,
+            batch_size: Some(100.0),
+            bootstrap_resamples: None,
+        };
+        AdaptiveSampling::from_input(Some(&input), 100, frozen_clock).unwrap()
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_once_the_half_width_is_within_tolerance() {
+        let sampling = sampling(0.05, 20000.0, None);
+        // Half-width at p = 0.5 is about 0.096 after 100 paths and 0.049 after 400.
+        assert_eq!(sampling.stop_reason(100, 50), None);
+        assert_eq!(sampling.stop_reason(300, 150), None);
+        // Only checked at the end of a batch.
+        assert_eq!(sampling.stop_reason(399, 200), None);
+        assert_eq!(sampling.stop_reason(400, 200), Some("tolerance"));
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_when_the_path_budget_runs_out() {
+        let sampling = sampling(0.001, 250.0, None);
+        assert_eq!(sampling.max_paths(), 250);
+        assert_eq!(sampling.stop_reason(200, 100), None);
+        // The budget ends mid-batch.
+        assert_eq!(sampling.stop_reason(250, 125), Some("paths"));
+        assert_eq!(sampling.progress(125, None), 0.5);
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_at_the_deadline_on_the_injected_clock() {
+        let sampling = sampling(0.001, 20000.0, Some(0.0));
+        assert_eq!(sampling.started(), Some(0.0));
+        assert_eq!(sampling.stop_reason(150, 75), None);
+        assert_eq!(sampling.stop_reason(200, 100), Some("time"));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::accounts::{AccountBalances, AccountModel, TAX_DEFERRED};
+use crate::calculations::summarize;
+use crate::engine::{ConversionPlan, ConversionYear};
+use crate::replay::{PathReplay, PlanFlows, ReplayOptions};
+
+const DEFAULT_SAMPLE_COUNT: usize = 200;
+const DEFAULT_GRID_STEPS: f64 = 8.0;
+const MAX_GRID_STEPS: f64 = 40.0;
+const REFINEMENT_PASSES: usize = 3;
+// Conversions usually stop once required minimum distributions begin.
+const DEFAULT_LAST_CONVERSION_AGE: f64 = 73.0;
+
+#[derive(Clone, Copy)]
+enum Objective {
+    TerminalWealth,
+    LifetimeTax,
+}
+
+#[derive(Clone, Copy)]
+struct Evaluation {
+    median_after_tax_wealth: f64,
+    median_lifetime_tax: f64,
+}
+
+impl Evaluation {
+    fn score(&self, objective: Objective) -> f64 {
+        match objective {
+            Objective::TerminalWealth => self.median_after_tax_wealth,
+            Objective::LifetimeTax => -self.median_lifetime_tax,
+        }
+    }
+}
+
+fn evaluate(
+    replay: &PathReplay,
+    model: &AccountModel,
+    flows: &PlanFlows,
+    sample_count: usize,
+    conversions: &[f64],
+) -> Evaluation {
+    let options = ReplayOptions {
+        conversions,
+        ..ReplayOptions::default()
+    };
+    let (wealth, taxes): (Vec<f64>, Vec<f64>) = (0..sample_count)
+        .map(|sim| {
+            let ledger = replay
+                .run_path(sim, flows.path(sim), &replay.rules, &options)
+                .state
+                .ledger;
+            let deferred_tax = ledger.deferred_tax(model);
+            (
+                ledger.total() - deferred_tax,
+                ledger.taxes_paid + deferred_tax,
+            )
+        })
+        .unzip();
+    Evaluation {
+        median_after_tax_wealth: summarize(&wealth).p50,
+        median_lifetime_tax: summarize(&taxes).p50,
+    }
+}
+
+/// Searches yearly tax-deferred to tax-free conversion amounts over the stored paths, each
+/// replayed through the simulation's month step with the conversions made at the start of
+/// each simulated year: first the best level amount across the window, then one-step
+/// coordinate moves per year until no single change improves the objective. After-tax wealth
+/// and lifetime tax both count the tax still owed on the end balances.
+pub fn optimize_conversions(replay: &PathReplay) -> Option<ConversionPlan> {
+    let input = replay.plan.input;
+    let model = replay.rules.account_model?;
+    let optimizer = input.conversion_optimizer.as_ref()?;
+    let objective = match optimizer.objective.as_deref() {
+        Some("lifetimeTax") => Objective::LifetimeTax,
+        _ => Objective::TerminalWealth,
+    };
+
+    let years = replay.months().div_ceil(12);
+    let from_age = optimizer.from_age.unwrap_or(input.retirement_age);
+    let to_age = optimizer
+        .to_age
+        .unwrap_or(DEFAULT_LAST_CONVERSION_AGE.max(from_age + 1.0));
+    let first_year = (from_age - input.current_age).ceil().max(0.0) as usize;
+    let end_year = ((to_age - input.current_age).ceil().max(0.0) as usize).min(years);
+    let deferred_balance = AccountBalances::new(input).balances[TAX_DEFERRED];
+    if replay.path_count() == 0 || first_year >= end_year || deferred_balance <= 0.0 {
+        return None;
+    }
+
+    let window_years = (end_year - first_year) as f64;
+    let max_amount = optimizer
+        .max_annual_amount
+        .unwrap_or(2.0 * deferred_balance / window_years)
+        .max(0.0);
+    let step = optimizer
+        .step
+        .filter(|step| *step > 0.0)
+        .unwrap_or(max_amount / DEFAULT_GRID_STEPS)
+        .max(max_amount / MAX_GRID_STEPS);
+    if max_amount <= 0.0 || step <= 0.0 {
+        return None;
+    }
+    let levels = (max_amount / step).floor() as usize;
+
+    let sample_count = optimizer
+        .sample_count
+        .unwrap_or(DEFAULT_SAMPLE_COUNT)
+        .clamp(1, replay.path_count());
+    let flows = replay.flows(&replay.plan);
+    let evaluate_schedule =
+        |conversions: &[f64]| evaluate(replay, model, &flows, sample_count, conversions);
+
+    let mut conversions = vec![0.0; years];
+    let baseline = evaluate_schedule(&conversions);
+    let mut best = baseline.score(objective);
+    let mut best_evaluation = baseline;
+
+    let mut best_level = 0;
+    for level in 1..=levels {
+        let mut candidate = vec![0.0; years];
+        candidate[first_year..end_year].fill(level as f64 * step);
+        let evaluation = evaluate_schedule(&candidate);
+        if evaluation.score(objective) > best {
+            best = evaluation.score(objective);
+            best_evaluation = evaluation;
+            best_level = level;
+        }
+    }
+    conversions[first_year..end_year].fill(best_level as f64 * step);
+
+    for _ in 0..REFINEMENT_PASSES {
+        let mut improved = false;
+        for year in first_year..end_year {
+            for delta in [-step, step] {
+                let amount = conversions[year] + delta;
+                if !(-1e-9..=max_amount + 1e-9).contains(&amount) {
+                    continue;
+                }
+                let mut candidate = conversions.clone();
+                candidate[year] = amount.max(0.0);
+                let evaluation = evaluate_schedule(&candidate);
+                if evaluation.score(objective) > best {
+                    best = evaluation.score(objective);
+                    best_evaluation = evaluation;
+                    conversions = candidate;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    Some(ConversionPlan {
+        objective: match objective {
+            Objective::TerminalWealth => "terminalWealth",
+            Objective::LifetimeTax => "lifetimeTax",
+        }
+        .to_string(),
+        schedule: (first_year..end_year)
+            .map(|year| ConversionYear {
+                age: input.current_age + year as f64,
+                amount: conversions[year],
+            })
+            .collect(),
+        median_after_tax_wealth: best_evaluation.median_after_tax_wealth,
+        baseline_median_after_tax_wealth: baseline.median_after_tax_wealth,
+        median_lifetime_tax: best_evaluation.median_lifetime_tax,
+        baseline_median_lifetime_tax: baseline.median_lifetime_tax,
+        improvement: best - baseline.score(objective),
+    })
+}
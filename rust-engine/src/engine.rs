@@ -1,17 +1,7 @@
 use crate::calculations::{clamp, PercentileSeries, RandomSource};
+use crate::regimes::stationary_distribution;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct RegimeModel {
-    pub stay_growth: f64,
-    pub stay_crisis: f64,
-    pub growth_mean: f64,
-    pub growth_std: f64,
-    pub crisis_mean: f64,
-    pub crisis_std: f64,
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ReturnMoments {
@@ -22,6 +12,35 @@ pub struct ReturnMoments {
     pub kurtosis: f64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegimeStateEstimate {
+    // Annualized from the fitted frequency.
+    pub mean: f64,
+    pub volatility: f64,
+    pub stationary_probability: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegimeFitSummary {
+    pub frequency: String,
+    pub states: Vec<RegimeStateEstimate>,
+    pub transition_matrix: Vec<Vec<f64>>,
+    // Per observation of the fitted history, one probability per state.
+    pub smoothed_probabilities: Vec<Vec<f64>>,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPercentiles {
+    pub taxable: PercentileSeries<Vec<f64>>,
+    pub tax_deferred: PercentileSeries<Vec<f64>>,
+    pub tax_free: PercentileSeries<Vec<f64>>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SimulationResult {
@@ -29,10 +48,185 @@ pub struct SimulationResult {
     pub ages: Vec<f64>,
     pub retire_month: u32,
     pub percentiles: PercentileSeries<Vec<f64>>,
+    // Balance bands in nominal currency, when `nominalOutput` is set.
+    pub nominal_percentiles: Option<PercentileSeries<Vec<f64>>>,
+    pub spending_percentiles: Option<PercentileSeries<Vec<f64>>>,
+    pub account_percentiles: Option<AccountPercentiles>,
+    pub allocation_percentiles: Option<Vec<AssetWeightPercentiles>>,
     pub final_percentiles: PercentileSeries<f64>,
     pub retire_percentiles: PercentileSeries<f64>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetWeightPercentiles {
+    pub id: String,
+    pub weights: PercentileSeries<Vec<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalancingOutcome {
+    pub policy: String,
+    pub success_probability: f64,
+    pub median_terminal_balance: f64,
+    pub median_annual_turnover: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalancingStats {
+    pub policy: String,
+    // Share of the portfolio sold per year to rebalance, across paths.
+    pub annual_turnover: PercentileSeries<f64>,
+    pub comparison: Vec<RebalancingOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GlidePathOutcome {
+    pub name: String,
+    // Equity weight at retirement and at the end of the horizon.
+    pub retirement_equity: f64,
+    pub end_equity: f64,
+    pub success_probability: f64,
+    pub p10_terminal_balance: f64,
+    pub median_terminal_balance: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StressScenarioOutcome {
+    pub name: String,
+    // Years after retirement that followed the scenario.
+    pub years: usize,
+    pub success_probability: f64,
+    pub p10_terminal_balance: f64,
+    pub median_terminal_balance: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StressTestStats {
+    // The same replay without a forced scenario.
+    pub unconditional_success_probability: f64,
+    pub unconditional_median_terminal_balance: f64,
+    pub scenarios: Vec<StressScenarioOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvergenceStats {
+    pub path_count: usize,
+    pub success_standard_error: f64,
+    // 95% Wilson interval and its half-width.
+    pub success_interval: ConfidenceInterval,
+    pub success_half_width: f64,
+    // 95% path-bootstrap intervals; `None` when resampling is off.
+    pub bootstrap_resamples: usize,
+    pub fi_target: Option<ConfidenceInterval>,
+    pub final_low: Option<ConfidenceInterval>,
+    pub final_median: Option<ConfidenceInterval>,
+    pub final_high: Option<ConfidenceInterval>,
+    pub retire_median: Option<ConfidenceInterval>,
+    // "tolerance", "paths" or "time" when adaptive sampling ran.
+    pub stopped_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitivityOutcome {
+    pub success_probability: f64,
+    pub median_terminal_wealth: f64,
+    pub fi_target: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitivityRow {
+    pub parameter: String,
+    pub base_value: f64,
+    pub low_value: f64,
+    pub high_value: f64,
+    // Changes from the base run.
+    pub low: SensitivityOutcome,
+    pub high: SensitivityOutcome,
+    pub success_probability_swing: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitivityStats {
+    pub relative_change: f64,
+    pub base: SensitivityOutcome,
+    // Ranked by success probability swing, then by median terminal wealth swing.
+    pub rows: Vec<SensitivityRow>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SolverSolution {
+    pub target_success_probability: f64,
+    // Binomial standard error of the replayed success probability at the target.
+    pub standard_error: f64,
+    // `None` when no value in the search range reaches the target.
+    pub value: Option<f64>,
+    pub success_probability: Option<f64>,
+    // 95% interval: the values reaching the target ± 1.96 standard errors.
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SolverStats {
+    pub path_count: usize,
+    pub retirement_age: Option<SolverSolution>,
+    pub spending_scale: Option<SolverSolution>,
+    // Withdrawal mode under which `spendingScale` was not solved: portfolio-percentage modes
+    // set spending from the balance, so scaling the planned spending has no effect.
+    pub spending_scale_unsupported: Option<String>,
+    pub required_savings: Option<SolverSolution>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SalaryStats {
+    // Yearly gross salary in the last working month.
+    pub final_salary: PercentileSeries<f64>,
+    // Lifetime real totals across paths.
+    pub lifetime_salary: PercentileSeries<f64>,
+    pub employee_contributions: PercentileSeries<f64>,
+    pub employer_contributions: PercentileSeries<f64>,
+    // Median lifetime contributions into each account.
+    pub taxable_contributions: f64,
+    pub tax_deferred_contributions: f64,
+    pub tax_free_contributions: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoricalCohort {
+    pub start_index: usize,
+    pub start_month: Option<String>,
+    // The horizon ran past the end of the history and continued from its start.
+    pub wrapped: bool,
+    pub success: bool,
+    pub ending_balance: f64,
+    // Largest peak-to-trough fall of the real balance, as a fraction of the peak.
+    pub worst_drawdown: f64,
+    // Lowest real balance from retirement on, and the age it was reached.
+    pub min_balance: f64,
+    pub min_balance_age: f64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SequenceRiskBucket {
@@ -45,9 +239,79 @@ pub struct SequenceRiskBucket {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RuinSurface {
-    pub retirement_ages: Vec<usize>,
+    pub retirement_ages: Vec<f64>,
     pub spending_multipliers: Vec<f64>,
+    // Indexed [spending multiplier][retirement age].
     pub ruin_probabilities: Vec<Vec<f64>>,
+    pub standard_errors: Vec<Vec<f64>>,
+    pub sample_size: usize,
+    pub target_contour: Option<RuinContour>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RuinContour {
+    pub target_success_probability: f64,
+    // Per retirement age; `None` when the grid does not cross the target.
+    pub spending_multipliers: Vec<Option<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionYear {
+    pub age: f64,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionPlan {
+    pub objective: String,
+    pub schedule: Vec<ConversionYear>,
+    pub median_after_tax_wealth: f64,
+    pub baseline_median_after_tax_wealth: f64,
+    pub median_lifetime_tax: f64,
+    pub baseline_median_lifetime_tax: f64,
+    // Gain in median after-tax wealth, or median tax saved, against no conversions.
+    pub improvement: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimingAgeOutcome {
+    pub claiming_age: f64,
+    pub annual_benefit: f64,
+    pub success_probability: f64,
+    pub median_bequest: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimingAnalysis {
+    pub source_id: String,
+    pub outcomes: Vec<ClaimingAgeOutcome>,
+    pub best_claiming_age: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingStats {
+    pub mean_annual: PercentileSeries<f64>,
+    pub min_annual: PercentileSeries<f64>,
+    pub cut_probability: f64,
+    pub raise_probability: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MortalityStats {
+    pub ruin_before_death_probability: f64,
+    pub survival_to_horizon_probability: f64,
+    pub death_age: PercentileSeries<f64>,
+    pub depletion_age: PercentileSeries<f64>,
+    pub years_depleted_before_death: PercentileSeries<f64>,
+    pub expected_bequest: f64,
+    pub bequest: PercentileSeries<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -60,6 +324,7 @@ pub struct SummaryStats {
     pub fi_probability_swr: f64,
     pub fi_probability_p95: f64,
     pub return_moments: ReturnMoments,
+    pub regime_fit: Option<RegimeFitSummary>,
     pub sequence_risk: Vec<SequenceRiskBucket>,
     pub ruin_surface: RuinSurface,
     pub shortfall_low: f64,
@@ -74,56 +339,84 @@ pub struct SummaryStats {
     pub final_high: f64,
     pub retire_median: f64,
     pub retire_high: f64,
+    pub mortality: Option<MortalityStats>,
+    pub lifetime_taxes: Option<PercentileSeries<f64>>,
+    pub conversion_plan: Option<ConversionPlan>,
+    pub claiming_analysis: Option<ClaimingAnalysis>,
+    pub rebalancing: Option<RebalancingStats>,
+    pub glide_path_comparison: Option<Vec<GlidePathOutcome>>,
+    pub historical_cohorts: Option<Vec<HistoricalCohort>>,
+    pub stress_tests: Option<StressTestStats>,
+    pub salary: Option<SalaryStats>,
+    pub solver: Option<SolverStats>,
+    // Percentiles of each path's perfect withdrawal rate.
+    pub perfect_withdrawal_rate: Option<PercentileSeries<f64>>,
+    // Paths with nothing left at retirement, counted in those percentiles at a rate of 0.
+    pub perfect_withdrawal_rate_depleted_paths: usize,
+    pub convergence: ConvergenceStats,
 }
 
 const MIN_STATE_PROBABILITY: f64 = 0.001;
-const FI_TARGET_SUCCESS_PROBABILITY: f64 = 0.95;
+pub const FI_TARGET_SUCCESS_PROBABILITY: f64 = 0.95;
 
 pub fn clamp_transition_probability(value: f64) -> f64 {
     clamp(value, MIN_STATE_PROBABILITY, 1.0 - MIN_STATE_PROBABILITY)
 }
 
-pub fn get_growth_stationary_probability(stay_growth: f64, stay_crisis: f64) -> f64 {
-    let denominator = 2.0 - stay_growth - stay_crisis;
-    if denominator <= 1e-9 {
-        return 0.5;
+/// Draws the starting state from the chain's stationary distribution.
+pub fn initial_regime_state(transition: &[Vec<f64>], rng: &mut RandomSource) -> usize {
+    let distribution = stationary_distribution(transition);
+    if distribution.len() == 2 {
+        return if rng.random() < clamp_transition_probability(distribution[0]) {
+            0
+        } else {
+            1
+        };
     }
-    (1.0 - stay_crisis) / denominator
-}
-
-pub fn initial_regime_state(stay_growth: f64, stay_crisis: f64, rng: &mut RandomSource) -> u8 {
-    let growth_probability =
-        clamp_transition_probability(get_growth_stationary_probability(stay_growth, stay_crisis));
-    if rng.random() < growth_probability {
-        0
-    } else {
-        1
+    let draw = rng.random();
+    let mut cumulative = 0.0;
+    for (state, probability) in distribution.iter().enumerate() {
+        cumulative += probability;
+        if draw < cumulative {
+            return state;
+        }
     }
+    distribution.len().saturating_sub(1)
 }
 
+/// One step of the chain: stays with the row's diagonal probability, otherwise moves to the
+/// other states in order of their transition probabilities' cumulative sum.
 pub fn transition_regime_state(
-    current_state: u8,
-    stay_growth: f64,
-    stay_crisis: f64,
+    current_state: usize,
+    transition: &[Vec<f64>],
     rng: &mut RandomSource,
-) -> u8 {
-    if current_state == 0 {
-        if rng.random() < stay_growth {
-            0
-        } else {
-            1
+) -> usize {
+    let row = &transition[current_state];
+    let draw = rng.random();
+    let mut cumulative = row[current_state];
+    if draw < cumulative {
+        return current_state;
+    }
+    let mut last = current_state;
+    for (state, probability) in row.iter().enumerate() {
+        if state == current_state {
+            continue;
         }
-    } else {
-        if rng.random() < stay_crisis {
-            1
-        } else {
-            0
+        cumulative += probability;
+        last = state;
+        if draw < cumulative {
+            return state;
         }
     }
+    last
 }
 
 pub fn draw_cornish_fisher_score(skewness: f64, kurtosis: f64, rng: &mut RandomSource) -> f64 {
-    let z = rng.normal(0.0, 1.0);
+    cornish_fisher_score(rng.normal(0.0, 1.0), skewness, kurtosis)
+}
+
+/// Maps a standard normal draw `z` onto a score with the target skewness and kurtosis.
+pub fn cornish_fisher_score(z: f64, skewness: f64, kurtosis: f64) -> f64 {
     let excess_kurtosis = clamp(kurtosis - 3.0, 0.0, 8.0);
     let bounded_skew = clamp(skewness, -1.5, 1.5);
 
@@ -149,6 +442,47 @@ pub fn draw_monthly_return_shaped(
     monthly_mean + monthly_std * draw_cornish_fisher_score(skewness, kurtosis, rng)
 }
 
+/// AR(1) persistence of monthly inflation around its regime mean. Monthly innovations are
+/// scaled so a 12-month sum keeps the variance of independent draws, leaving the annual
+/// variability as configured while shocks carry into the following years.
+pub struct InflationPersistence {
+    coefficient: f64,
+    innovation_scale: f64,
+    deviation: Option<f64>,
+}
+
+impl InflationPersistence {
+    pub fn new(annual_coefficient: f64) -> Self {
+        let coefficient = annual_coefficient.clamp(0.0, 0.99).powf(1.0 / 12.0);
+        let sum_variance = 12.0
+            + 2.0
+                * (1..12)
+                    .map(|lag| (12 - lag) as f64 * coefficient.powi(lag))
+                    .sum::<f64>();
+        Self {
+            coefficient,
+            innovation_scale: (12.0 / sum_variance).sqrt(),
+            deviation: None,
+        }
+    }
+
+    /// Monthly inflation from an independent draw around `monthly_mean`.
+    pub fn apply(&mut self, monthly_mean: f64, draw: f64) -> f64 {
+        if self.coefficient == 0.0 {
+            return draw;
+        }
+        let shock = (draw - monthly_mean) * self.innovation_scale;
+        let deviation = match self.deviation {
+            Some(previous) => {
+                self.coefficient * previous + (1.0 - self.coefficient.powi(2)).sqrt() * shock
+            }
+            None => shock,
+        };
+        self.deviation = Some(deviation);
+        monthly_mean + deviation
+    }
+}
+
 pub fn draw_student_t(df: f64, rng: &mut RandomSource) -> f64 {
     let safe_df = df.round().max(3.0) as usize;
     let z = rng.normal(0.0, 1.0);
@@ -1,5 +1,10 @@
-use crate::structs::{IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod};
-use std::collections::HashSet;
+use crate::claiming::ClaimingRule;
+use crate::indexation::IndexationRule;
+use crate::regimes::{clamp_transition_matrix, RegimeFit};
+use crate::salary::{SalaryFlows, SalaryModel};
+use crate::structs::{
+    HouseholdMember, IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod,
+};
 
 pub fn apply_moment_targeting(
     value: f64,
@@ -20,214 +25,121 @@ pub fn apply_moment_targeting(
     target_mean + normalized * safe_target_std
 }
 
-pub fn detect_regimes(annual_returns: &[f64]) -> Vec<u8> {
-    let len = annual_returns.len().max(1) as f64;
-    let mean = annual_returns.iter().sum::<f64>() / len;
-    let variance = annual_returns
-        .iter()
-        .map(|&v| (v - mean).powi(2))
-        .sum::<f64>()
-        / len;
-    let std_dev = variance.max(0.0).sqrt();
-    let crisis_threshold = mean - 0.65 * std_dev;
+/// Labels each period with its most probable state under a Gaussian hidden Markov model with
+/// `states` regimes, ordered by mean (0 is growth, the last is crisis). Without a usable fit
+/// every period counts as growth.
+pub fn detect_regimes(returns: &[f64], states: usize) -> (Option<RegimeFit>, Vec<usize>) {
+    let fit = RegimeFit::fit(returns, states);
+    let labels = fit
+        .as_ref()
+        .map_or_else(|| vec![0; returns.len()], RegimeFit::labels);
+    (fit, labels)
+}
 
-    let mut labels: Vec<u8> = annual_returns
-        .iter()
-        .enumerate()
-        .map(|(index, &value)| {
-            let start = index.saturating_sub(2);
-            let window = &annual_returns[start..=index];
-            let window_len = window.len().max(1) as f64;
-            let window_mean = window.iter().sum::<f64>() / window_len;
-            let window_variance = window
-                .iter()
-                .map(|&v| (v - window_mean).powi(2))
-                .sum::<f64>()
-                / window_len;
-            let rolling_std = window_variance.max(0.0).sqrt();
-
-            let crisis_by_return = value <= crisis_threshold;
-            let crisis_by_volatility = rolling_std >= std_dev * 1.15;
-
-            if crisis_by_return || crisis_by_volatility {
-                1
-            } else {
-                0
-            }
-        })
-        .collect();
+// Copies of a period across the regime pools.
+const POOL_RESOLUTION: usize = 20;
 
-    if labels.len() > 2 {
-        for index in 1..labels.len() - 1 {
-            if labels[index] == 0 && labels[index - 1] == 1 && labels[index + 1] == 1 {
-                labels[index] = 1;
-            }
+/// Bootstrap pool per regime state.
+pub struct RegimePools<T> {
+    pub states: Vec<Vec<T>>,
+}
+
+impl<T> RegimePools<T> {
+    pub fn empty(states: usize) -> Self {
+        Self {
+            states: (0..states).map(|_| Vec::new()).collect(),
         }
     }
 
-    labels
+    pub fn pool(&self, state: usize) -> &[T] {
+        &self.states[state.min(self.states.len() - 1)]
+    }
 }
 
-pub fn detect_regimes_monthly(monthly_returns: &[f64]) -> Vec<u8> {
-    let len = monthly_returns.len().max(1) as f64;
-    let mean = monthly_returns.iter().sum::<f64>() / len;
-    let variance = monthly_returns
-        .iter()
-        .map(|&v| (v - mean).powi(2))
-        .sum::<f64>()
-        / len;
-    let std_dev = variance.max(0.0).sqrt();
-    let crisis_threshold = mean - 0.75 * std_dev;
-
-    let mut labels: Vec<u8> = monthly_returns
-        .iter()
-        .enumerate()
-        .map(|(index, &value)| {
-            let start = index.saturating_sub(5);
-            let window = &monthly_returns[start..=index];
-            let window_len = window.len().max(1) as f64;
-            let window_mean = window.iter().sum::<f64>() / window_len;
-            let window_variance = window
-                .iter()
-                .map(|&v| (v - window_mean).powi(2))
-                .sum::<f64>()
-                / window_len;
-            let rolling_std = window_variance.max(0.0).sqrt();
-
-            let crisis_by_return = value <= crisis_threshold;
-            let crisis_by_volatility = rolling_std >= std_dev * 1.2;
-
-            if crisis_by_return || crisis_by_volatility {
-                1
-            } else {
-                0
-            }
-        })
-        .collect();
-
-    if labels.len() > 2 {
-        for index in 1..labels.len() - 1 {
-            if labels[index] == 0 && labels[index - 1] == 1 && labels[index + 1] == 1 {
-                labels[index] = 1;
+/// Periods of each regime pool, repeated in proportion to their smoothed probability of that
+/// regime, so drawing from a pool at the regime's stationary frequency reproduces the history.
+/// The growth state takes whatever copies the others leave.
+fn regime_pool_indices(len: usize, states: usize, fit: Option<&RegimeFit>) -> Vec<Vec<usize>> {
+    let mut pools: Vec<Vec<usize>> = vec![Vec::new(); states];
+    match fit.filter(|fit| fit.states() == states) {
+        Some(fit) => {
+            for (index, probabilities) in fit.smoothed.iter().enumerate() {
+                let mut remaining = POOL_RESOLUTION;
+                for state in 1..states {
+                    let copies = ((probabilities[state] * POOL_RESOLUTION as f64).round() as usize)
+                        .min(remaining);
+                    pools[state].extend(std::iter::repeat_n(index, copies));
+                    remaining -= copies;
+                }
+                pools[0].extend(std::iter::repeat_n(index, remaining));
             }
         }
+        None => pools[0].extend(0..len),
     }
-
-    labels
-}
-
-pub struct RegimePools<T> {
-    pub growth: Vec<T>,
-    pub crisis: Vec<T>,
+    pools
 }
 
-pub fn bootstrap_pool_by_regime(annual_returns: &[f64], labels: &[u8]) -> RegimePools<f64> {
-    let mut growth = Vec::new();
-    let mut crisis = Vec::new();
-
-    for (index, &label) in labels.iter().enumerate() {
-        if label == 1 {
-            crisis.push(annual_returns[index]);
+/// Fills empty pools: the growth pool and middle states fall back to the whole history, the
+/// crisis pool to its worst `crisis_share` (at least `min_crisis`) periods.
+fn fill_empty_pools(
+    pools: &mut [Vec<usize>],
+    returns: &[f64],
+    crisis_share: f64,
+    min_crisis: usize,
+) {
+    let crisis = pools.len() - 1;
+    for (state, pool) in pools.iter_mut().enumerate() {
+        if !pool.is_empty() {
+            continue;
+        }
+        if state == 0 || state != crisis {
+            pool.extend(0..returns.len());
         } else {
-            growth.push(annual_returns[index]);
+            let mut indices: Vec<usize> = (0..returns.len()).collect();
+            indices.sort_by(|&a, &b| {
+                returns[a]
+                    .partial_cmp(&returns[b])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let take_count = min_crisis.max((returns.len() as f64 * crisis_share).floor() as usize);
+            pool.extend(indices.into_iter().take(take_count));
         }
     }
+}
 
-    let fallback_growth = if !growth.is_empty() {
-        growth
-    } else {
-        annual_returns.to_vec()
-    };
-
-    let fallback_crisis = if !crisis.is_empty() {
-        crisis
-    } else {
-        let mut sorted = annual_returns.to_vec();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        let take_count = 4.max((annual_returns.len() as f64 * 0.35).floor() as usize);
-        sorted.into_iter().take(take_count).collect()
-    };
-
+pub fn bootstrap_pool_by_regime(
+    annual_returns: &[f64],
+    states: usize,
+    fit: Option<&RegimeFit>,
+) -> RegimePools<f64> {
+    let mut pools = regime_pool_indices(annual_returns.len(), states, fit);
+    fill_empty_pools(&mut pools, annual_returns, 0.35, 4);
     RegimePools {
-        growth: fallback_growth,
-        crisis: fallback_crisis,
+        states: pools
+            .into_iter()
+            .map(|pool| {
+                pool.into_iter()
+                    .map(|index| annual_returns[index])
+                    .collect()
+            })
+            .collect(),
     }
 }
 
 pub fn bootstrap_indices_by_regime_monthly(
     monthly_returns: &[f64],
-    labels: &[u8],
+    states: usize,
+    fit: Option<&RegimeFit>,
 ) -> RegimePools<usize> {
-    let mut growth = Vec::new();
-    let mut crisis = Vec::new();
-
-    for (index, &label) in labels.iter().enumerate() {
-        if label == 1 {
-            crisis.push(index);
-        } else {
-            growth.push(index);
-        }
-    }
-
-    let fallback_growth = if !growth.is_empty() {
-        growth
-    } else {
-        (0..monthly_returns.len()).collect()
-    };
-
-    let fallback_crisis = if !crisis.is_empty() {
-        crisis
-    } else {
-        let mut indices: Vec<usize> = (0..monthly_returns.len()).collect();
-        indices.sort_by(|&a, &b| {
-            monthly_returns[a]
-                .partial_cmp(&monthly_returns[b])
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-        let take_count = 12.max((monthly_returns.len() as f64 * 0.3).floor() as usize);
-        indices.into_iter().take(take_count).collect()
-    };
-
-    RegimePools {
-        growth: fallback_growth,
-        crisis: fallback_crisis,
-    }
+    let mut pools = regime_pool_indices(monthly_returns.len(), states, fit);
+    fill_empty_pools(&mut pools, monthly_returns, 0.3, 12);
+    RegimePools { states: pools }
 }
 
-pub fn estimate_markov_stay_probabilities(labels: &[u8]) -> (f64, f64) {
-    let mut growth_stay = 0.0;
-    let mut growth_total = 0.0;
-    let mut crisis_stay = 0.0;
-    let mut crisis_total = 0.0;
-
-    for index in 1..labels.len() {
-        let prev = labels[index - 1];
-        let curr = labels[index];
-        if prev == 0 {
-            growth_total += 1.0;
-            if curr == 0 {
-                growth_stay += 1.0;
-            }
-        } else {
-            crisis_total += 1.0;
-            if curr == 1 {
-                crisis_stay += 1.0;
-            }
-        }
-    }
-
-    let stay_growth = crate::engine::clamp_transition_probability(if growth_total > 0.0 {
-        growth_stay / growth_total
-    } else {
-        0.88
-    });
-    let stay_crisis = crate::engine::clamp_transition_probability(if crisis_total > 0.0 {
-        crisis_stay / crisis_total
-    } else {
-        0.72
-    });
-
-    (stay_growth, stay_crisis)
+/// Monthly transition matrix from a fitted regime model, with stay probabilities clamped.
+pub fn estimate_markov_transition_matrix(fit: Option<&RegimeFit>) -> Option<Vec<Vec<f64>>> {
+    fit.filter(|fit| fit.states() >= 2)
+        .map(|fit| clamp_transition_matrix(&fit.transition))
 }
 
 pub fn monthly_returns_to_annual_series(monthly_returns: &[f64]) -> Vec<f64> {
@@ -246,9 +158,135 @@ pub fn monthly_returns_to_annual_series(monthly_returns: &[f64]) -> Vec<f64> {
     annual
 }
 
+#[derive(Clone)]
 pub struct CashflowArrays {
     pub monthly_net_flow: Vec<f64>,
+    pub monthly_income: Vec<f64>,
+    // Part of `monthly_income` from sources flagged `taxable` (entered before tax).
+    pub monthly_taxable_income: Vec<f64>,
+    pub monthly_spending: Vec<f64>,
     pub lump_sum_by_month: Vec<f64>,
+    // Salary contributions and employer money, included in `monthly_net_flow`.
+    pub monthly_contributions: Vec<f64>,
+    pub salary: Option<SalaryFlows>,
+    // Flows that are not continuously CPI-indexed, grouped by rule. The totals above value them
+    // at the rule's index under expected inflation.
+    pub indexed_flows: Vec<IndexedFlows>,
+    pub expected_price_index: Vec<f64>,
+    pub expected_inflation: f64,
+}
+
+#[derive(Clone)]
+pub struct IndexedFlows {
+    pub rule: IndexationRule,
+    // Monthly amounts in today's currency before any indexation.
+    pub income: Vec<f64>,
+    pub taxable_income: Vec<f64>,
+    pub spending: Vec<f64>,
+    pub expected_index: Vec<f64>,
+}
+
+impl CashflowArrays {
+    pub fn has_path_indexed_flows(&self) -> bool {
+        !self.indexed_flows.is_empty()
+    }
+
+    /// Salary contributions paid into each account in month `m`.
+    pub fn account_contributions(&self, m: usize) -> [f64; 3] {
+        self.salary.as_ref().map_or([0.0; 3], |salary| {
+            salary.by_account.each_ref().map(|series| series[m])
+        })
+    }
+
+    /// The same flows with the planned salary replaced by one path's salary.
+    pub fn with_salary(&self, mut salary: SalaryFlows) -> CashflowArrays {
+        let mut arrays = self.clone();
+        if let Some(planned) = &self.salary {
+            for m in 0..arrays.monthly_income.len() {
+                if planned.gross[m] == 0.0 {
+                    salary.clear_month(m);
+                }
+                arrays.monthly_income[m] += salary.take_home[m] - planned.take_home[m];
+                arrays.monthly_taxable_income[m] += salary.taxable[m] - planned.taxable[m];
+                arrays.monthly_contributions[m] = salary.contributions(m);
+                arrays.monthly_net_flow[m] = arrays.monthly_income[m] - arrays.monthly_spending[m]
+                    + arrays.monthly_contributions[m];
+            }
+        }
+        arrays.salary = Some(salary);
+        arrays
+    }
+
+    /// Revalues the rule-indexed flows of `months` at a path's rule indices and price index
+    /// instead of their expected values, starting from the expected-inflation `base`.
+    pub fn realize_months(
+        &mut self,
+        base: &CashflowArrays,
+        months: std::ops::Range<usize>,
+        rule_indices: &[f64],
+        price_index: f64,
+    ) {
+        let realized_deflator = 1.0 / price_index.max(1e-9);
+        for m in months {
+            self.monthly_income[m] = base.monthly_income[m];
+            self.monthly_taxable_income[m] = base.monthly_taxable_income[m];
+            self.monthly_spending[m] = base.monthly_spending[m];
+            for (group, rule_index) in base.indexed_flows.iter().zip(rule_indices) {
+                let shift = rule_index * realized_deflator
+                    - group.expected_index[m] / base.expected_price_index[m];
+                self.monthly_income[m] += group.income[m] * shift;
+                self.monthly_taxable_income[m] += group.taxable_income[m] * shift;
+                self.monthly_spending[m] += group.spending[m] * shift;
+            }
+            self.monthly_net_flow[m] =
+                self.monthly_income[m] - self.monthly_spending[m] + base.monthly_contributions[m];
+        }
+    }
+
+    /// Cash flows of a stored path with monthly inflation `inflation_path`.
+    pub fn with_realized_inflation(&self, inflation_path: &[f64]) -> CashflowArrays {
+        let mut realized = RealizedFlows::new(self);
+        let mut price_index = 1.0;
+        for (m, inflation) in inflation_path.iter().enumerate() {
+            realized.advance(self, m, price_index, m + 1);
+            price_index *= 1.0 + inflation;
+        }
+        realized.flows
+    }
+}
+
+/// Cash flows of one path as its inflation unfolds. Rule-indexed flows take their annual
+/// adjustments from the path's own price history.
+pub struct RealizedFlows {
+    pub flows: CashflowArrays,
+    price_history: Vec<f64>,
+    rule_indices: Vec<f64>,
+}
+
+impl RealizedFlows {
+    pub fn new(base: &CashflowArrays) -> Self {
+        Self {
+            flows: base.clone(),
+            price_history: Vec::with_capacity(base.monthly_income.len()),
+            rule_indices: vec![1.0; base.indexed_flows.len()],
+        }
+    }
+
+    /// Records the price index at the start of month `m`, applies any annual adjustment due
+    /// then, and revalues months `m..through` at the current indices.
+    pub fn advance(&mut self, base: &CashflowArrays, m: usize, price_index: f64, through: usize) {
+        self.price_history.push(price_index);
+        if m > 0 && m.is_multiple_of(12) {
+            for (group, rule_index) in base.indexed_flows.iter().zip(&mut self.rule_indices) {
+                let cpi = group
+                    .rule
+                    .measured_cpi(m, &self.price_history, base.expected_inflation);
+                *rule_index *= 1.0 + group.rule.adjustment(cpi);
+            }
+        }
+        self.flows
+            .realize_months(base, m..through, &self.rule_indices, price_index);
+    }
 }
 
 pub fn expected_inflation_index_at_age(input: &RetirementInput, age: f64) -> f64 {
@@ -270,18 +308,89 @@ pub fn spending_at_age(age: f64, spending_periods: &[SpendingPeriod], inflation_
         })
 }
 
-pub fn income_at_age(age: f64, income_sources: &[IncomeSource], inflation_index: f64) -> f64 {
+const DEFAULT_SURVIVOR_SPENDING_RATIO: f64 = 0.7;
+
+// Index of the group holding flows under `rule`, added on first use; `None` for flows that
+// stay constant in real terms.
+fn indexation_group(
+    groups: &mut Vec<IndexedFlows>,
+    rule: Option<IndexationRule>,
+    months: usize,
+    expected_inflation: f64,
+) -> Option<usize> {
+    let rule = rule?;
+    if let Some(index) = groups.iter().position(|group| group.rule == rule) {
+        return Some(index);
+    }
+    groups.push(IndexedFlows {
+        rule,
+        income: vec![0.0; months],
+        taxable_income: vec![0.0; months],
+        spending: vec![0.0; months],
+        expected_index: rule.expected_index(months, expected_inflation),
+    });
+    Some(groups.len() - 1)
+}
+
+struct ResolvedIncomeSource<'a> {
+    source: &'a IncomeSource,
+    from_age: f64,
+    to_age: f64,
+    // Owner's age minus the primary age; source ages are expressed in the owner's age.
+    age_offset: f64,
+    owner: Option<usize>,
+    // Early-claiming reduction or delay credit on the benefit.
+    amount_factor: f64,
+}
+
+fn resolve_income_sources<'a>(
+    input: &RetirementInput,
+    income_sources: &'a [IncomeSource],
+) -> Vec<ResolvedIncomeSource<'a>> {
+    let members = household_members(input);
     income_sources
         .iter()
-        .filter(|source| source.from_age <= age && source.to_age > age)
-        .fold(0.0, |sum, source| {
-            let is_inflation_adjusted = source.inflation_adjusted.unwrap_or(true);
-            sum + if is_inflation_adjusted {
-                source.yearly_amount
-            } else {
-                source.yearly_amount / inflation_index
+        .map(|source| {
+            let owner = source
+                .owner
+                .as_ref()
+                .and_then(|owner| members.iter().position(|member| &member.id == owner));
+            let (age_offset, retirement_age) = match owner {
+                Some(index) => (
+                    members[index].current_age - input.current_age,
+                    members[index].retirement_age,
+                ),
+                None => (0.0, input.retirement_age),
+            };
+            let (mut from_age, to_age) = match source.retirement_link.as_deref() {
+                Some("endsAtRetirement") => (source.from_age, retirement_age),
+                Some("startsAtRetirement") => (retirement_age, source.to_age),
+                _ => (source.from_age, source.to_age),
+            };
+            let mut amount_factor = 1.0;
+            if let Some(claiming) = &source.claiming {
+                let rule = ClaimingRule::from_input(claiming);
+                from_age = rule.claiming_age(claiming);
+                amount_factor = rule.benefit_factor(from_age);
+            }
+            ResolvedIncomeSource {
+                source,
+                from_age,
+                to_age,
+                age_offset,
+                owner,
+                amount_factor,
             }
         })
+        .collect()
+}
+
+pub fn household_members(input: &RetirementInput) -> &[HouseholdMember] {
+    input
+        .household
+        .as_ref()
+        .map(|household| household.members.as_slice())
+        .unwrap_or(&[])
 }
 
 pub fn build_cashflow_arrays(
@@ -291,15 +400,150 @@ pub fn build_cashflow_arrays(
     lump_sum_events: &[LumpSumEvent],
     months: u32,
 ) -> CashflowArrays {
-    let mut monthly_net_flow = vec![0.0; months as usize];
-    let mut lump_sum_by_month = vec![0.0; months as usize];
+    build_path_cashflow_arrays(
+        input,
+        spending_periods,
+        income_sources,
+        lump_sum_events,
+        months,
+        &[],
+    )
+}
+
+/// Cash flows for one path of a household. `death_months[i]` is the month household member `i`
+/// dies, or `None` when they outlive the horizon; missing entries mean the member is alive.
+pub fn build_path_cashflow_arrays(
+    input: &RetirementInput,
+    spending_periods: &[SpendingPeriod],
+    income_sources: &[IncomeSource],
+    lump_sum_events: &[LumpSumEvent],
+    months: u32,
+    death_months: &[Option<usize>],
+) -> CashflowArrays {
+    let members = household_members(input);
+    let survivor_spending_ratio = input
+        .household
+        .as_ref()
+        .and_then(|household| household.survivor_spending_ratio)
+        .unwrap_or(DEFAULT_SURVIVOR_SPENDING_RATIO)
+        .clamp(0.0, 1.0);
+    let resolved_sources = resolve_income_sources(input, income_sources);
+
+    let months_len = months as usize;
+    let mut monthly_net_flow = vec![0.0; months_len];
+    let mut monthly_income = vec![0.0; months_len];
+    let mut monthly_taxable_income = vec![0.0; months_len];
+    let mut monthly_spending = vec![0.0; months_len];
+    let mut lump_sum_by_month = vec![0.0; months_len];
+    let mut expected_price_index = vec![1.0; months_len];
+    let mut monthly_contributions = vec![0.0; months_len];
+    let mut salary =
+        SalaryModel::from_input(input).map(|model| model.flows(input.current_age, months_len, &[]));
+    let mut indexed_flows: Vec<IndexedFlows> = Vec::new();
+    let source_groups: Vec<Option<usize>> = resolved_sources
+        .iter()
+        .map(|resolved| {
+            let rule = IndexationRule::from_input(
+                resolved.source.indexation.as_ref(),
+                resolved.source.inflation_adjusted.unwrap_or(true),
+            );
+            indexation_group(&mut indexed_flows, rule, months_len, input.inflation_mean)
+        })
+        .collect();
+    let period_groups: Vec<Option<usize>> = spending_periods
+        .iter()
+        .map(|period| {
+            let rule = IndexationRule::from_input(
+                period.indexation.as_ref(),
+                period.inflation_adjusted.unwrap_or(true),
+            );
+            indexation_group(&mut indexed_flows, rule, months_len, input.inflation_mean)
+        })
+        .collect();
 
-    for m in 0..months {
+    for m in 0..months_len {
         let age = input.current_age + (m as f64) / 12.0;
         let inflation_index = expected_inflation_index_at_age(input, age);
-        let income = income_at_age(age, income_sources, inflation_index);
-        let spending = spending_at_age(age, spending_periods, inflation_index);
-        monthly_net_flow[m as usize] = (income - spending) / 12.0;
+        expected_price_index[m] = inflation_index;
+        let is_alive = |member: usize| {
+            death_months
+                .get(member)
+                .copied()
+                .flatten()
+                .is_none_or(|death_month| m < death_month)
+        };
+        let alive_count = (0..members.len())
+            .filter(|&member| is_alive(member))
+            .count();
+        if !members.is_empty() && alive_count == 0 {
+            if let Some(salary) = salary.as_mut() {
+                salary.clear_month(m);
+            }
+            continue;
+        }
+
+        let (mut income, mut taxable_income) = salary.as_ref().map_or((0.0, 0.0), |salary| {
+            (salary.take_home[m] * 12.0, salary.taxable[m] * 12.0)
+        });
+        monthly_contributions[m] = salary
+            .as_ref()
+            .map_or(0.0, |salary| salary.contributions(m));
+        for (resolved, group) in resolved_sources.iter().zip(&source_groups) {
+            let owner_age = age + resolved.age_offset;
+            if resolved.from_age > owner_age || resolved.to_age <= owner_age {
+                continue;
+            }
+            let mut amount = resolved.source.yearly_amount * resolved.amount_factor;
+            if resolved.owner.is_some_and(|owner| !is_alive(owner)) {
+                amount *= resolved
+                    .source
+                    .survivor_fraction
+                    .unwrap_or(0.0)
+                    .clamp(0.0, 1.0);
+            }
+            let taxable = resolved.source.taxable.unwrap_or(false);
+            let real_amount = match *group {
+                Some(index) => {
+                    let group = &mut indexed_flows[index];
+                    group.income[m] += amount / 12.0;
+                    if taxable {
+                        group.taxable_income[m] += amount / 12.0;
+                    }
+                    amount * group.expected_index[m] / inflation_index
+                }
+                None => amount,
+            };
+            income += real_amount;
+            if taxable {
+                taxable_income += real_amount;
+            }
+        }
+
+        let spending_scale = if alive_count < members.len() {
+            survivor_spending_ratio
+        } else {
+            1.0
+        };
+        let mut spending = 0.0;
+        for (period, group) in spending_periods.iter().zip(&period_groups) {
+            if period.from_age > age || period.to_age <= age {
+                continue;
+            }
+            let amount = period.yearly_amount * spending_scale;
+            spending += match *group {
+                Some(index) => {
+                    let group = &mut indexed_flows[index];
+                    group.spending[m] += amount / 12.0;
+                    amount * group.expected_index[m] / inflation_index
+                }
+                None => amount,
+            };
+        }
+
+        monthly_net_flow[m] = (income - spending) / 12.0 + monthly_contributions[m];
+        monthly_income[m] = income / 12.0;
+        monthly_taxable_income[m] = taxable_income / 12.0;
+        monthly_spending[m] = spending / 12.0;
     }
 
     for event in lump_sum_events {
@@ -311,6 +555,14 @@ pub fn build_cashflow_arrays(
 
     CashflowArrays {
         monthly_net_flow,
+        monthly_income,
+        monthly_taxable_income,
+        monthly_spending,
         lump_sum_by_month,
+        monthly_contributions,
+        salary,
+        indexed_flows,
+        expected_price_index,
+        expected_inflation: input.inflation_mean,
     }
 }
@@ -0,0 +1,210 @@
+use crate::structs::IndexationInput;
+
+const DEFAULT_TRIPLE_LOCK_FLOOR: f64 = 0.025;
+
+/// How a non-fully-indexed income source or spending period moves with prices. Adjustments
+/// are applied once a year, at the start of each simulated year, from the CPI change over the
+/// 12 months ending `lag_months` earlier.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IndexationRule {
+    // Fixed in nominal terms.
+    Fixed,
+    // CPI − `minus`, clamped to [`floor`, `cap`].
+    Cpi {
+        cap: f64,
+        floor: f64,
+        minus: f64,
+        lag_months: usize,
+    },
+    // The highest of CPI, nominal earnings growth (CPI + `earnings_growth`) and `floor`. The
+    // earnings leg is a fixed real growth rate, not the salary model's wage path, so it only
+    // matters when `earnings_growth` is positive.
+    TripleLock {
+        floor: f64,
+        earnings_growth: f64,
+        lag_months: usize,
+    },
+}
+
+impl IndexationRule {
+    /// Rejects a `mode` none of the rules handles, rather than running it as CPI indexation.
+    pub fn validate_mode(indexation: &IndexationInput) -> Result<(), String> {
+        match indexation.mode.as_deref() {
+            None | Some("cpi") | Some("fixed") | Some("none") | Some("tripleLock") => Ok(()),
+            Some(mode) => Err(format!(
+                "unknown indexation mode '{}'; expected 'cpi', 'fixed' or 'tripleLock'",
+                mode
+            )),
+        }
+    }
+
+    /// `None` for continuous full CPI indexation, which keeps the flow constant in real terms.
+    pub fn from_input(
+        indexation: Option<&IndexationInput>,
+        inflation_adjusted: bool,
+    ) -> Option<Self> {
+        let Some(indexation) = indexation else {
+            return (!inflation_adjusted).then_some(IndexationRule::Fixed);
+        };
+        let lag_months = indexation.lag_months.unwrap_or(0.0).max(0.0).round() as usize;
+        match indexation.mode.as_deref() {
+            Some("fixed") | Some("none") => Some(IndexationRule::Fixed),
+            Some("tripleLock") => Some(IndexationRule::TripleLock {
+                floor: indexation.floor.unwrap_or(DEFAULT_TRIPLE_LOCK_FLOOR),
+                earnings_growth: indexation.earnings_growth.unwrap_or(0.0),
+                lag_months,
+            }),
+            // "cpi"; other modes are rejected by `validate_mode` before a run.
+            _ => Some(IndexationRule::Cpi {
+                cap: indexation.cap.unwrap_or(f64::INFINITY),
+                floor: indexation.floor.unwrap_or(f64::NEG_INFINITY),
+                minus: indexation.minus.unwrap_or(0.0),
+                lag_months,
+            }),
+        }
+    }
+
+    fn lag_months(&self) -> usize {
+        match *self {
+            IndexationRule::Fixed => 0,
+            IndexationRule::Cpi { lag_months, .. }
+            | IndexationRule::TripleLock { lag_months, .. } => lag_months,
+        }
+    }
+
+    /// Annual increase given the measured CPI change.
+    pub fn adjustment(&self, cpi: f64) -> f64 {
+        match *self {
+            IndexationRule::Fixed => 0.0,
+            IndexationRule::Cpi {
+                cap, floor, minus, ..
+            } => (cpi - minus).min(cap).max(floor),
+            IndexationRule::TripleLock {
+                floor,
+                earnings_growth,
+                ..
+            } => (cpi + earnings_growth.max(0.0)).max(floor),
+        }
+    }
+
+    /// CPI change measured for the adjustment at month `m` from the price index at the start of
+    /// each month so far. Windows reaching before the simulation use `expected_inflation`.
+    pub fn measured_cpi(&self, m: usize, price_history: &[f64], expected_inflation: f64) -> f64 {
+        match m.checked_sub(self.lag_months() + 12) {
+            Some(start) => price_history[start + 12] / price_history[start] - 1.0,
+            None => expected_inflation,
+        }
+    }
+
+    /// The rule's index at each month when inflation runs at `expected_inflation` every year.
+    pub fn expected_index(&self, months: usize, expected_inflation: f64) -> Vec<f64> {
+        let yearly_factor = 1.0 + self.adjustment(expected_inflation);
+        (0..months)
+            .map(|m| yearly_factor.powi((m / 12) as i32))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(mode: &str, configure: impl FnOnce(&mut IndexationInput)) -> IndexationRule {
+        let mut input = IndexationInput {
+            mode: Some(mode.to_string()),
+            cap: None,
+            floor: None,
+            minus: None,
+            lag_months: None,
+            earnings_growth: None,
+        };
+        configure(&mut input);
+        IndexationRule::from_input(Some(&input), true).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn cpi_rules_apply_the_cap_floor_and_minus() {
+        // A UK DB pension capped at 2.5% and never cut.
+        let capped = rule("cpi", |input| {
+            input.cap = Some(0.025);
+            input.floor = Some(0.0);
+        });
+        assert_close(capped.adjustment(0.05), 0.025);
+        assert_close(capped.adjustment(0.01), 0.01);
+        assert_close(capped.adjustment(-0.02), 0.0);
+
+        // CPI − 1% with a zero floor.
+        let cpi_minus = rule("cpi", |input| {
+            input.minus = Some(0.01);
+            input.floor = Some(0.0);
+        });
+        assert_close(cpi_minus.adjustment(0.03), 0.02);
+        assert_close(cpi_minus.adjustment(0.005), 0.0);
+
+        // Without bounds deflation cuts the amount.
+        assert_close(rule("cpi", |_| {}).adjustment(-0.02), -0.02);
+        assert_close(rule("fixed", |_| {}).adjustment(0.05), 0.0);
+    }
+
+    #[test]
+    fn triple_lock_takes_the_highest_leg() {
+        let triple_lock = rule("tripleLock", |_| {});
+        assert_close(triple_lock.adjustment(0.01), DEFAULT_TRIPLE_LOCK_FLOOR);
+        assert_close(triple_lock.adjustment(0.04), 0.04);
+
+        let with_earnings = rule("tripleLock", |input| input.earnings_growth = Some(0.01));
+        assert_close(with_earnings.adjustment(0.02), 0.03);
+        // A negative earnings leg never pulls the rise below CPI.
+        let negative = rule("tripleLock", |input| input.earnings_growth = Some(-0.01));
+        assert_close(negative.adjustment(0.04), 0.04);
+    }
+
+    #[test]
+    fn measured_cpi_looks_back_over_the_lagged_year() {
+        // Flat prices for a year, then 1% a month.
+        let price_history: Vec<f64> = (0..=36)
+            .map(|m: i32| 1.01_f64.powi((m - 12).max(0)))
+            .collect();
+        let year_of_inflation = 1.01_f64.powi(12) - 1.0;
+
+        let no_lag = rule("cpi", |_| {});
+        assert_close(no_lag.measured_cpi(12, &price_history, 0.02), 0.0);
+        assert_close(
+            no_lag.measured_cpi(24, &price_history, 0.02),
+            year_of_inflation,
+        );
+
+        // Six months of lag sees half of the inflating year; `lagMonths` is rounded.
+        let lagged = rule("cpi", |input| input.lag_months = Some(5.6));
+        assert_close(
+            lagged.measured_cpi(24, &price_history, 0.02),
+            1.01_f64.powi(6) - 1.0,
+        );
+        // Windows reaching before the simulation use the expected rate.
+        assert_close(lagged.measured_cpi(12, &price_history, 0.02), 0.02);
+    }
+
+    #[test]
+    fn expected_index_steps_once_a_year() {
+        let capped = rule("cpi", |input| input.cap = Some(0.025));
+        let index = capped.expected_index(25, 0.03);
+        assert_close(index[11], 1.0);
+        assert_close(index[12], 1.025);
+        assert_close(index[24], 1.025 * 1.025);
+
+        assert_eq!(
+            IndexationRule::from_input(None, false),
+            Some(IndexationRule::Fixed)
+        );
+        assert_eq!(IndexationRule::from_input(None, true), None);
+    }
+}
@@ -1,11 +1,31 @@
 use serde::{Deserialize, Serialize};
+pub mod accounts;
+pub mod assets;
+pub mod backtest;
+pub mod bootstrap;
 pub mod calculations;
+pub mod claiming;
+pub mod convergence;
+pub mod conversion;
 pub mod engine;
 pub mod engine2;
+pub mod indexation;
+pub mod mortality;
+pub mod regimes;
+pub mod replay;
+pub mod salary;
+pub mod sensitivity;
 pub mod simulation;
+pub mod solver;
 pub mod stats;
+pub mod stress;
 pub mod structs;
+pub mod tax;
+pub mod withdrawal;
 
+use crate::assets::{Portfolio, MIN_HISTORY_MONTHS};
+use crate::bootstrap::BootstrapScheme;
+use crate::indexation::IndexationRule;
 use crate::structs::{IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod};
 use wasm_bindgen::prelude::*;
 
@@ -14,9 +34,136 @@ use wasm_bindgen::prelude::*;
 pub struct WasmResult {
     pub simulation: crate::engine::SimulationResult,
     pub stats: crate::engine::SummaryStats,
+    pub spending_stats: Option<crate::engine::SpendingStats>,
     pub sim_count: u32,
 }
 
+fn validate_mortality(input: &RetirementInput) -> Result<(), JsValue> {
+    let member_mortality = input.household.iter().flat_map(|household| {
+        household
+            .members
+            .iter()
+            .filter_map(|member| member.mortality.as_ref().map(|m| (m, member.current_age)))
+    });
+    for (mortality, current_age) in input
+        .mortality
+        .iter()
+        .map(|mortality| (mortality, input.current_age))
+        .chain(member_mortality)
+    {
+        crate::mortality::LifeTable::from_input(mortality, current_age)
+            .map_err(|message| JsValue::from_str(&message))?;
+    }
+    Ok(())
+}
+
+// Historical modes resample history; they must not quietly fall back to drawn returns.
+fn validate_history(input: &RetirementInput) -> Result<(), JsValue> {
+    let mode = input.simulation_mode.as_deref().unwrap_or("historical");
+    let Some(scheme) = BootstrapScheme::from_mode(mode) else {
+        return Ok(());
+    };
+    let blended_months = input
+        .historical_monthly_returns
+        .iter()
+        .flatten()
+        .filter(|value| value.is_finite())
+        .count();
+    let portfolio = Portfolio::from_input(input);
+    if blended_months >= MIN_HISTORY_MONTHS
+        && portfolio
+            .as_ref()
+            .is_some_and(|portfolio| !portfolio.has_history())
+    {
+        return Err(JsValue::from_str(&format!(
+            "simulationMode '{}' needs at least {} aligned months of historicalMonthlyReturns \
+             on every portfolio asset; use a parametric mode to draw asset returns",
+            mode, MIN_HISTORY_MONTHS
+        )));
+    }
+    // Other schemes fall back to the annual history; a sequential backtest has no such path.
+    let history_months = match &portfolio {
+        Some(portfolio) if portfolio.has_history() => portfolio
+            .blended_history()
+            .iter()
+            .filter(|value| value.is_finite())
+            .count(),
+        _ => blended_months,
+    };
+    if scheme == BootstrapScheme::Sequential && history_months < MIN_HISTORY_MONTHS {
+        return Err(JsValue::from_str(&format!(
+            "simulationMode 'historicalSequential' needs at least {} months of \
+             historicalMonthlyReturns, got {}",
+            MIN_HISTORY_MONTHS, history_months
+        )));
+    }
+    // A replay of history must not mix the actual returns with drawn inflation.
+    if scheme == BootstrapScheme::Sequential && input.historical_monthly_inflation.is_none() {
+        return Err(JsValue::from_str(
+            "simulationMode 'historicalSequential' needs historicalMonthlyInflation aligned \
+             with the return history",
+        ));
+    }
+    // The inflation series is indexed by the return history's months, so it must cover
+    // exactly the same months.
+    if let Some(inflation) = input.historical_monthly_inflation.as_ref() {
+        let (returns_field, returns_len) = match (&portfolio, input.portfolio.as_ref()) {
+            (Some(portfolio), Some(portfolio_input)) if portfolio.has_history() => (
+                "the portfolio asset historicalMonthlyReturns",
+                portfolio_input
+                    .assets
+                    .iter()
+                    .map(|asset| {
+                        asset
+                            .historical_monthly_returns
+                            .as_ref()
+                            .map_or(0, Vec::len)
+                    })
+                    .min()
+                    .unwrap_or(0),
+            ),
+            _ => (
+                "historicalMonthlyReturns",
+                input
+                    .historical_monthly_returns
+                    .as_ref()
+                    .map_or(0, Vec::len),
+            ),
+        };
+        if history_months >= MIN_HISTORY_MONTHS && inflation.len() != returns_len {
+            return Err(JsValue::from_str(&format!(
+                "historicalMonthlyInflation has {} months but {} has {}; the series must \
+                 line up month for month",
+                inflation.len(),
+                returns_field,
+                returns_len
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_indexation(
+    spending_periods: &[SpendingPeriod],
+    income_sources: &[IncomeSource],
+) -> Result<(), JsValue> {
+    spending_periods
+        .iter()
+        .filter_map(|period| period.indexation.as_ref())
+        .chain(
+            income_sources
+                .iter()
+                .filter_map(|source| source.indexation.as_ref()),
+        )
+        .try_for_each(IndexationRule::validate_mode)
+        .map_err(|message| JsValue::from_str(&message))
+}
+
+fn validate_input(input: &RetirementInput) -> Result<(), JsValue> {
+    validate_mortality(input)?;
+    validate_history(input)
+}
+
 #[wasm_bindgen]
 pub fn run_monte_carlo(
     input_val: JsValue,
@@ -28,10 +175,12 @@ pub fn run_monte_carlo(
     progress_callback: Option<js_sys::Function>,
 ) -> Result<JsValue, JsValue> {
     let input: RetirementInput = serde_wasm_bindgen::from_value(input_val)?;
+    validate_input(&input)?;
     let spending_periods: Vec<SpendingPeriod> =
         serde_wasm_bindgen::from_value(spending_periods_val)?;
     let income_sources: Vec<IncomeSource> = serde_wasm_bindgen::from_value(income_sources_val)?;
     let lumpsum_events: Vec<LumpSumEvent> = serde_wasm_bindgen::from_value(lumpsum_events_val)?;
+    validate_indexation(&spending_periods, &income_sources)?;
 
     let cb_wrapper: Option<Box<dyn Fn(f64)>> = progress_callback.map(|cb| {
         Box::new(move |progress: f64| {
@@ -47,13 +196,52 @@ pub fn run_monte_carlo(
         months,
         retire_month,
         cb_wrapper.as_deref(),
-    );
+    )
+    .map_err(|message| JsValue::from_str(&message))?;
 
     let res = WasmResult {
         simulation: wrapper.simulation,
         stats: wrapper.stats,
+        spending_stats: wrapper.spending_stats,
         sim_count: wrapper.sim_count,
     };
 
     Ok(serde_wasm_bindgen::to_value(&res)?)
 }
+
+/// Tornado table of how much each input and cash flow moves the outcome (see `sensitivity`).
+#[wasm_bindgen]
+pub fn run_sensitivity(
+    input_val: JsValue,
+    spending_periods_val: JsValue,
+    income_sources_val: JsValue,
+    lumpsum_events_val: JsValue,
+    months: u32,
+    progress_callback: Option<js_sys::Function>,
+) -> Result<JsValue, JsValue> {
+    let input: RetirementInput = serde_wasm_bindgen::from_value(input_val)?;
+    validate_input(&input)?;
+    let spending_periods: Vec<SpendingPeriod> =
+        serde_wasm_bindgen::from_value(spending_periods_val)?;
+    let income_sources: Vec<IncomeSource> = serde_wasm_bindgen::from_value(income_sources_val)?;
+    let lumpsum_events: Vec<LumpSumEvent> = serde_wasm_bindgen::from_value(lumpsum_events_val)?;
+    validate_indexation(&spending_periods, &income_sources)?;
+
+    let cb_wrapper: Option<Box<dyn Fn(f64)>> = progress_callback.map(|cb| {
+        Box::new(move |progress: f64| {
+            let _ = cb.call1(&JsValue::NULL, &JsValue::from_f64(progress));
+        }) as Box<dyn Fn(f64)>
+    });
+
+    let stats = crate::sensitivity::run_sensitivity_analysis(
+        &input,
+        &spending_periods,
+        &income_sources,
+        &lumpsum_events,
+        months,
+        cb_wrapper.as_deref(),
+    )
+    .map_err(|message| JsValue::from_str(&message))?;
+
+    Ok(serde_wasm_bindgen::to_value(&stats)?)
+}
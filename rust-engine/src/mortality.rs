@@ -0,0 +1,267 @@
+use crate::calculations::RandomSource;
+use crate::structs::MortalityInput;
+
+pub const MAX_AGE: usize = 120;
+const DEFAULT_COHORT_IMPROVEMENT: f64 = 0.01;
+// Death ages come from their own stream so enabling mortality leaves market paths unchanged.
+pub const MORTALITY_SEED_OFFSET: f64 = 7919.0;
+
+// Gompertz-Makeham parameters (modal age, dispersion, background hazard) per region and sex.
+// These are rounded illustrative values, not fitted to a published table; pass `lifeTable`
+// for planning against an official one.
+const GOMPERTZ_PARAMETERS: [(&str, &str, f64, f64, f64); 8] = [
+    ("us", "male", 85.4, 10.0, 0.0005),
+    ("us", "female", 88.7, 10.0, 0.0005),
+    ("uk", "male", 86.1, 10.0, 0.0005),
+    ("uk", "female", 89.1, 10.0, 0.0005),
+    ("eu", "male", 85.7, 10.0, 0.0005),
+    ("eu", "female", 90.0, 10.0, 0.0005),
+    ("world", "male", 82.6, 10.0, 0.002),
+    ("world", "female", 86.2, 10.0, 0.002),
+];
+
+#[derive(Clone, Debug)]
+pub struct LifeTable {
+    // One-year death probabilities indexed by whole age, 0..=MAX_AGE.
+    qx: Vec<f64>,
+}
+
+fn normalize_country(country: &str) -> &'static str {
+    match country.to_ascii_lowercase().as_str() {
+        "us" | "usd" | "usa" => "us",
+        "uk" | "gbp" | "gb" => "uk",
+        "eu" | "eur" => "eu",
+        _ => "world",
+    }
+}
+
+fn gompertz_qx(age: f64, modal_age: f64, dispersion: f64, background: f64) -> f64 {
+    let cumulative = |x: f64| ((x - modal_age) / dispersion).exp();
+    1.0 - (-background - (cumulative(age + 1.0) - cumulative(age))).exp()
+}
+
+impl LifeTable {
+    pub fn from_qx(mut qx: Vec<f64>) -> Self {
+        qx.resize(MAX_AGE + 1, 1.0);
+        for q in qx.iter_mut() {
+            *q = q.clamp(0.0, 1.0);
+        }
+        qx[MAX_AGE] = 1.0;
+        Self { qx }
+    }
+
+    pub fn builtin(country: &str, sex: &str) -> Self {
+        let country = normalize_country(country);
+        let table_for = |sex: &str| {
+            let (_, _, modal_age, dispersion, background) = GOMPERTZ_PARAMETERS
+                .iter()
+                .find(|(c, s, ..)| *c == country && *s == sex)
+                .copied()
+                .unwrap_or(GOMPERTZ_PARAMETERS[0]);
+            (0..=MAX_AGE)
+                .map(|age| gompertz_qx(age as f64, modal_age, dispersion, background))
+                .collect::<Vec<f64>>()
+        };
+
+        let qx = match sex {
+            "male" | "female" => table_for(sex),
+            _ => table_for("male")
+                .iter()
+                .zip(table_for("female"))
+                .map(|(male, female)| (male + female) / 2.0)
+                .collect(),
+        };
+        Self::from_qx(qx)
+    }
+
+    /// Parses a CSV life table with an `age` column and either a `qx` column or
+    /// `male` / `female` (optionally `unisex`) columns. Lines starting with `#` are ignored;
+    /// ages past the last row die with certainty.
+    pub fn parse(text: &str, sex: &str) -> Result<Self, String> {
+        let mut rows = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let (_, header) = rows.next().ok_or("life table is empty")?;
+        let columns: Vec<String> = header
+            .split(',')
+            .map(|column| column.trim().to_ascii_lowercase())
+            .collect();
+        let find = |name: &str| columns.iter().position(|column| column == name);
+
+        let age_column = find("age").ok_or("life table needs an 'age' column")?;
+        let qx_columns: Vec<usize> = match find("qx").or_else(|| find(sex)) {
+            Some(column) => vec![column],
+            None => match (find("male"), find("female")) {
+                (Some(male), Some(female)) => vec![male, female],
+                _ => return Err(format!("life table has no 'qx' or '{}' column", sex)),
+            },
+        };
+
+        let mut qx = vec![f64::NAN; MAX_AGE + 1];
+        let mut last_age = None;
+        for (line_number, line) in rows {
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            let parse_cell = |column: usize| -> Result<f64, String> {
+                cells
+                    .get(column)
+                    .and_then(|cell| cell.parse::<f64>().ok())
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("invalid value on life table line {}", line_number))
+            };
+
+            let age = parse_cell(age_column)?;
+            if age < 0.0 || age.fract() != 0.0 {
+                return Err(format!("invalid age on life table line {}", line_number));
+            }
+            let mut death_probability = 0.0;
+            for &column in &qx_columns {
+                death_probability += parse_cell(column)?;
+            }
+            death_probability /= qx_columns.len() as f64;
+            if !(0.0..=1.0).contains(&death_probability) {
+                return Err(format!(
+                    "death probability outside [0, 1] on life table line {}",
+                    line_number
+                ));
+            }
+            let age = (age as usize).min(MAX_AGE);
+            qx[age] = death_probability;
+            last_age = Some(last_age.map_or(age, |last: usize| last.max(age)));
+        }
+
+        let last_age = last_age.ok_or("life table has no data rows")?;
+        // Ages before the first row take its value, gaps carry the previous row forward.
+        let mut previous = qx.iter().copied().find(|q| !q.is_nan()).unwrap_or(1.0);
+        for q in qx.iter_mut().take(last_age + 1) {
+            if q.is_nan() {
+                *q = previous;
+            } else {
+                previous = *q;
+            }
+        }
+        qx.truncate(last_age + 1);
+        Ok(Self::from_qx(qx))
+    }
+
+    pub fn from_input(mortality: &MortalityInput, current_age: f64) -> Result<Self, String> {
+        let sex = mortality.sex.as_deref().unwrap_or("unisex");
+        let is_cohort = mortality.table_type.as_deref() == Some("cohort");
+        let (table, default_improvement) = match &mortality.life_table {
+            Some(text) => (Self::parse(text, sex)?, 0.0),
+            None => (
+                Self::builtin(mortality.country.as_deref().unwrap_or("world"), sex),
+                DEFAULT_COHORT_IMPROVEMENT,
+            ),
+        };
+
+        let improvement =
+            mortality
+                .improvement_rate
+                .unwrap_or(if is_cohort { default_improvement } else { 0.0 });
+        Ok(table.with_improvement(current_age, improvement))
+    }
+
+    /// Projects a period table onto the cohort aged `current_age` today by compounding an
+    /// annual mortality improvement over the years until each age is reached.
+    pub fn with_improvement(mut self, current_age: f64, annual_improvement: f64) -> Self {
+        if annual_improvement.abs() < 1e-12 {
+            return self;
+        }
+        let factor = (1.0 - annual_improvement).clamp(0.0, 2.0);
+        for (age, q) in self.qx.iter_mut().enumerate() {
+            if *q >= 1.0 {
+                continue;
+            }
+            let years_ahead = (age as f64 - current_age).max(0.0);
+            *q = (*q * factor.powf(years_ahead)).clamp(0.0, 1.0);
+        }
+        self
+    }
+
+    pub fn death_probability(&self, age: usize) -> f64 {
+        self.qx[age.min(MAX_AGE)]
+    }
+
+    pub fn draw_death_age(&self, current_age: f64, rng: &mut RandomSource) -> f64 {
+        let mut age = current_age.max(0.0);
+        while (age as usize) < MAX_AGE {
+            let whole_age = age.floor();
+            let exposure = whole_age + 1.0 - age;
+            if rng.random() < self.death_probability(whole_age as usize) * exposure {
+                return age + rng.random() * exposure;
+            }
+            age = whole_age + 1.0;
+        }
+        age + rng.random()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_a_qx_column_and_fills_gaps() {
+        let table = LifeTable::parse(
+            "# period table\nAge, qx\n\n60, 0.01\n62, 0.03\n63, 0.05\n",
+            "male",
+        )
+        .unwrap();
+        // Ages before the first row take its value; the gap at 61 carries 60 forward.
+        assert_eq!(table.death_probability(0), 0.01);
+        assert_eq!(table.death_probability(61), 0.01);
+        assert_eq!(table.death_probability(62), 0.03);
+        assert_eq!(table.death_probability(63), 0.05);
+        // Past the last row everyone dies.
+        assert_eq!(table.death_probability(64), 1.0);
+        assert_eq!(table.death_probability(MAX_AGE + 5), 1.0);
+    }
+
+    #[test]
+    fn parse_picks_the_sex_column_or_averages_male_and_female() {
+        let text = "age,male,female\n70,0.02,0.01\n71,0.04,0.02";
+        let male = LifeTable::parse(text, "male").unwrap();
+        let female = LifeTable::parse(text, "female").unwrap();
+        let unisex = LifeTable::parse(text, "unisex").unwrap();
+        assert_eq!(male.death_probability(71), 0.04);
+        assert_eq!(female.death_probability(71), 0.02);
+        assert!((unisex.death_probability(70) - 0.015).abs() < 1e-12);
+        assert!((unisex.death_probability(71) - 0.03).abs() < 1e-12);
+
+        // An explicit unisex column is used as given.
+        let table = LifeTable::parse("age,male,female,unisex\n70,0.02,0.01,0.012", "unisex");
+        assert_eq!(table.unwrap().death_probability(70), 0.012);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_tables() {
+        let error = |text: &str| LifeTable::parse(text, "female").unwrap_err();
+        assert_eq!(error("# only a comment\n"), "life table is empty");
+        assert_eq!(error("qx\n0.1"), "life table needs an 'age' column");
+        assert_eq!(
+            error("age,male\n60,0.1"),
+            "life table has no 'qx' or 'female' column"
+        );
+        assert_eq!(error("age,qx\n"), "life table has no data rows");
+        assert_eq!(
+            error("age,qx\n60,0.1\n61,abc"),
+            "invalid value on life table line 3"
+        );
+        assert_eq!(
+            error("age,qx\n60,0.1\n61"),
+            "invalid value on life table line 3"
+        );
+        assert_eq!(
+            error("age,qx\n60.5,0.1"),
+            "invalid age on life table line 2"
+        );
+        assert_eq!(error("age,qx\n-1,0.1"), "invalid age on life table line 2");
+        assert_eq!(
+            error("age,qx\n60,1.5"),
+            "death probability outside [0, 1] on life table line 2"
+        );
+    }
+}
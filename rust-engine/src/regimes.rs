@@ -0,0 +1,658 @@
+use crate::engine::{clamp_transition_probability, RegimeFitSummary, RegimeStateEstimate};
+use crate::structs::RegimeModelInput;
+use std::f64::consts::PI;
+
+const MAX_ITERATIONS: usize = 200;
+const TOLERANCE: f64 = 1e-8;
+const INITIAL_STAY_PROBABILITY: f64 = 0.9;
+// Observations needed per state before a fit is attempted.
+const MIN_OBSERVATIONS_PER_STATE: usize = 10;
+// State volatilities are kept above this share of the overall volatility so no state can
+// collapse onto a single observation.
+const MIN_STD_RATIO: f64 = 0.05;
+const MIN_DENSITY: f64 = 1e-300;
+const DEFAULT_STAY_PROBABILITY: f64 = 0.9;
+
+/// Long-run share of time in each state of a transition matrix.
+pub fn stationary_distribution(transition: &[Vec<f64>]) -> Vec<f64> {
+    let n = transition.len();
+    if n == 2 {
+        let denominator = 2.0 - transition[0][0] - transition[1][1];
+        if denominator <= 1e-9 {
+            return vec![0.5, 0.5];
+        }
+        let growth = (1.0 - transition[1][1]) / denominator;
+        return vec![growth, 1.0 - growth];
+    }
+    let mut distribution = vec![1.0 / n as f64; n];
+    for _ in 0..1000 {
+        let next: Vec<f64> = (0..n)
+            .map(|j| (0..n).map(|i| distribution[i] * transition[i][j]).sum())
+            .collect();
+        let change: f64 = next
+            .iter()
+            .zip(&distribution)
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        distribution = next;
+        if change < 1e-12 {
+            break;
+        }
+    }
+    distribution
+}
+
+/// Clamps each stay probability and rescales the row's moves to the other states to match.
+pub fn clamp_transition_matrix(transition: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = transition.len();
+    if n < 2 {
+        return vec![vec![1.0]; n];
+    }
+    transition
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let stay = clamp_transition_probability(row[i]);
+            let leaving: f64 = row
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, p)| p.max(0.0))
+                .sum();
+            row.iter()
+                .enumerate()
+                .map(|(j, p)| {
+                    if j == i {
+                        stay
+                    } else if leaving > 0.0 {
+                        (1.0 - stay) * p.max(0.0) / leaving
+                    } else {
+                        (1.0 - stay) / (n - 1) as f64
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct RegimeState {
+    pub name: String,
+    // Annual return mean and volatility while in the state.
+    pub mean: f64,
+    pub std: f64,
+    pub inflation_mean: Option<f64>,
+    pub inflation_variability: Option<f64>,
+}
+
+/// Market regimes ordered by mean return, highest first, with an annual transition matrix.
+/// The first state plays the growth role and the last the crisis role.
+#[derive(Clone, Debug)]
+pub struct RegimeModel {
+    pub states: Vec<RegimeState>,
+    pub transition: Vec<Vec<f64>>,
+    // Input index of each state, so per-state inputs given in input order can follow it.
+    pub input_order: Vec<usize>,
+}
+
+impl RegimeModel {
+    /// Uses `states` and `transitionMatrix` when given; otherwise maps the two-state
+    /// growth/crisis fields. Rows that do not match the state count fall back to a 0.9 stay
+    /// probability split evenly across the other states.
+    pub fn from_input(input: &RegimeModelInput) -> Self {
+        let Some(state_inputs) = input.states.as_ref().filter(|states| !states.is_empty()) else {
+            return Self {
+                states: vec![
+                    RegimeState {
+                        name: "growth".to_string(),
+                        mean: input.growth_mean,
+                        std: input.growth_std,
+                        inflation_mean: None,
+                        inflation_variability: None,
+                    },
+                    RegimeState {
+                        name: "crisis".to_string(),
+                        mean: input.crisis_mean,
+                        std: input.crisis_std,
+                        inflation_mean: None,
+                        inflation_variability: None,
+                    },
+                ],
+                transition: clamp_transition_matrix(&[
+                    vec![input.stay_growth, 1.0 - input.stay_growth],
+                    vec![1.0 - input.stay_crisis, input.stay_crisis],
+                ]),
+                input_order: vec![0, 1],
+            };
+        };
+
+        let n = state_inputs.len();
+        let states: Vec<RegimeState> = state_inputs
+            .iter()
+            .enumerate()
+            .map(|(index, state)| RegimeState {
+                name: state
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("state{}", index)),
+                mean: state.mean,
+                std: state.std.max(0.0),
+                inflation_mean: state.inflation_mean,
+                inflation_variability: state.inflation_variability.map(|value| value.max(0.0)),
+            })
+            .collect();
+        let transition: Vec<Vec<f64>> = (0..n)
+            .map(|i| {
+                let row = input
+                    .transition_matrix
+                    .as_ref()
+                    .and_then(|matrix| matrix.get(i))
+                    .filter(|row| row.len() == n && row.iter().all(|p| p.is_finite()));
+                match row {
+                    Some(row) => row.clone(),
+                    None => (0..n)
+                        .map(|j| {
+                            if i == j {
+                                DEFAULT_STAY_PROBABILITY
+                            } else {
+                                (1.0 - DEFAULT_STAY_PROBABILITY) / (n - 1).max(1) as f64
+                            }
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| states[b].mean.total_cmp(&states[a].mean));
+        Self {
+            states: order.iter().map(|&i| states[i].clone()).collect(),
+            transition: clamp_transition_matrix(
+                &order
+                    .iter()
+                    .map(|&i| order.iter().map(|&j| transition[i][j]).collect())
+                    .collect::<Vec<Vec<f64>>>(),
+            ),
+            input_order: order,
+        }
+    }
+
+    /// Reorders a per-state vector given in input order to the model's state order. States
+    /// the vector has no entry for are `None`.
+    pub fn in_model_order(&self, values: &[f64]) -> Vec<Option<f64>> {
+        self.input_order
+            .iter()
+            .map(|&index| values.get(index).copied())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn crisis_state(&self) -> usize {
+        self.len().saturating_sub(1)
+    }
+
+    /// Monthly transitions: each stay probability is the twelfth root of the annual one and
+    /// the rest of the row keeps the annual proportions between the other states.
+    pub fn monthly_transition(&self) -> Vec<Vec<f64>> {
+        let monthly: Vec<Vec<f64>> = self
+            .transition
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let stay = row[i].powf(1.0 / 12.0);
+                let leaving = 1.0 - row[i];
+                row.iter()
+                    .enumerate()
+                    .map(|(j, p)| {
+                        if i == j {
+                            stay
+                        } else if leaving > 0.0 {
+                            (1.0 - stay) * p / leaving
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        clamp_transition_matrix(&monthly)
+    }
+
+    /// Annual inflation mean of each state when none is given: the crisis state sits
+    /// `spread` above the others, weighted by its stationary probability so the long-run
+    /// mixture keeps the mean at `mean`. The spread is capped so the split alone stays within
+    /// 80% of the inflation `variability`.
+    pub fn default_inflation_means(&self, mean: f64, variability: f64, spread: f64) -> Vec<f64> {
+        if self.len() < 2 {
+            return vec![mean; self.len()];
+        }
+        let crisis_state = self.crisis_state();
+        let crisis_prob = self.stationary_distribution()[crisis_state];
+        let other_prob = 1.0 - crisis_prob;
+        let max_spread = (variability.powi(2) / (other_prob * crisis_prob)).sqrt();
+        let spread = spread.min(max_spread * 0.8);
+        (0..self.len())
+            .map(|state| {
+                if state == crisis_state {
+                    mean + other_prob * spread
+                } else {
+                    mean - crisis_prob * spread
+                }
+            })
+            .collect()
+    }
+
+    pub fn stationary_distribution(&self) -> Vec<f64> {
+        stationary_distribution(&self.transition)
+    }
+}
+
+/// Gaussian hidden Markov model fitted by Baum-Welch (EM). States are ordered by mean,
+/// highest first, so state 0 is growth and the last state is the crisis regime.
+#[derive(Clone, Debug)]
+pub struct RegimeFit {
+    pub means: Vec<f64>,
+    pub stds: Vec<f64>,
+    // `transition[i][j]` is the probability of moving from state i to state j.
+    pub transition: Vec<Vec<f64>>,
+    pub initial: Vec<f64>,
+    // Smoothed probability of each state, per observation.
+    pub smoothed: Vec<Vec<f64>>,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+}
+
+fn density(value: f64, mean: f64, std: f64) -> f64 {
+    let z = (value - mean) / std;
+    ((-0.5 * z * z).exp() / (std * (2.0 * PI).sqrt())).max(MIN_DENSITY)
+}
+
+struct Pass {
+    smoothed: Vec<Vec<f64>>,
+    // Expected transition counts summed over time.
+    transitions: Vec<Vec<f64>>,
+    log_likelihood: f64,
+}
+
+impl RegimeFit {
+    pub fn states(&self) -> usize {
+        self.means.len()
+    }
+
+    /// Scaled forward-backward pass: smoothed state probabilities, expected transition counts
+    /// and the log-likelihood.
+    fn forward_backward(&self, observations: &[f64]) -> Pass {
+        let n = self.states();
+        let len = observations.len();
+        let emissions: Vec<Vec<f64>> = observations
+            .iter()
+            .map(|&value| {
+                (0..n)
+                    .map(|state| density(value, self.means[state], self.stds[state]))
+                    .collect()
+            })
+            .collect();
+
+        let mut forward = vec![vec![0.0; n]; len];
+        let mut scales = vec![0.0; len];
+        for t in 0..len {
+            for j in 0..n {
+                let prior = if t == 0 {
+                    self.initial[j]
+                } else {
+                    (0..n)
+                        .map(|i| forward[t - 1][i] * self.transition[i][j])
+                        .sum()
+                };
+                forward[t][j] = prior * emissions[t][j];
+            }
+            scales[t] = forward[t].iter().sum::<f64>().max(MIN_DENSITY);
+            for value in forward[t].iter_mut() {
+                *value /= scales[t];
+            }
+        }
+
+        let mut backward = vec![vec![1.0; n]; len];
+        for t in (0..len.saturating_sub(1)).rev() {
+            for i in 0..n {
+                backward[t][i] = (0..n)
+                    .map(|j| self.transition[i][j] * emissions[t + 1][j] * backward[t + 1][j])
+                    .sum::<f64>()
+                    / scales[t + 1];
+            }
+        }
+
+        let mut transitions = vec![vec![0.0; n]; n];
+        for t in 0..len.saturating_sub(1) {
+            for (i, row) in transitions.iter_mut().enumerate() {
+                for (j, count) in row.iter_mut().enumerate() {
+                    *count += forward[t][i]
+                        * self.transition[i][j]
+                        * emissions[t + 1][j]
+                        * backward[t + 1][j]
+                        / scales[t + 1];
+                }
+            }
+        }
+
+        let smoothed = forward
+            .iter()
+            .zip(&backward)
+            .map(|(alpha, beta)| {
+                let row: Vec<f64> = alpha.iter().zip(beta).map(|(a, b)| a * b).collect();
+                let total = row.iter().sum::<f64>().max(MIN_DENSITY);
+                row.into_iter().map(|value| value / total).collect()
+            })
+            .collect();
+
+        Pass {
+            smoothed,
+            transitions,
+            log_likelihood: scales.iter().map(|scale| scale.ln()).sum(),
+        }
+    }
+
+    /// Fits `states` regimes to the series. Starts from equal-count quantile buckets and runs
+    /// EM until the log-likelihood stops improving. `None` when the series is too short or flat.
+    pub fn fit(observations: &[f64], states: usize) -> Option<Self> {
+        let len = observations.len();
+        if states == 0 || len < states * MIN_OBSERVATIONS_PER_STATE {
+            return None;
+        }
+        let mean = observations.iter().sum::<f64>() / len as f64;
+        let std = (observations
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / len as f64)
+            .sqrt();
+        if !std.is_finite() || std <= 1e-12 {
+            return None;
+        }
+        let min_std = std * MIN_STD_RATIO;
+
+        let mut sorted = observations.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let means = (0..states)
+            .map(|state| {
+                let bucket = &sorted[state * len / states..(state + 1) * len / states];
+                bucket.iter().sum::<f64>() / bucket.len() as f64
+            })
+            .collect();
+        let off_diagonal = if states > 1 {
+            (1.0 - INITIAL_STAY_PROBABILITY) / (states - 1) as f64
+        } else {
+            0.0
+        };
+        let mut fit = Self {
+            means,
+            stds: vec![std; states],
+            transition: (0..states)
+                .map(|i| {
+                    (0..states)
+                        .map(|j| {
+                            if states == 1 {
+                                1.0
+                            } else if i == j {
+                                INITIAL_STAY_PROBABILITY
+                            } else {
+                                off_diagonal
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+            initial: vec![1.0 / states as f64; states],
+            smoothed: vec![],
+            log_likelihood: f64::NEG_INFINITY,
+            iterations: 0,
+        };
+
+        let mut pass = fit.forward_backward(observations);
+        for iteration in 1..=MAX_ITERATIONS {
+            for state in 0..states {
+                let weight: f64 = pass.smoothed.iter().map(|row| row[state]).sum();
+                if weight <= MIN_DENSITY {
+                    continue;
+                }
+                let state_mean = pass
+                    .smoothed
+                    .iter()
+                    .zip(observations)
+                    .map(|(row, value)| row[state] * value)
+                    .sum::<f64>()
+                    / weight;
+                let variance = pass
+                    .smoothed
+                    .iter()
+                    .zip(observations)
+                    .map(|(row, value)| row[state] * (value - state_mean).powi(2))
+                    .sum::<f64>()
+                    / weight;
+                fit.means[state] = state_mean;
+                fit.stds[state] = variance.sqrt().max(min_std);
+
+                let leaving: f64 = pass.transitions[state].iter().sum();
+                if leaving > MIN_DENSITY {
+                    for (probability, count) in fit.transition[state]
+                        .iter_mut()
+                        .zip(&pass.transitions[state])
+                    {
+                        *probability = count / leaving;
+                    }
+                }
+            }
+            fit.initial = pass.smoothed[0].clone();
+            fit.iterations = iteration;
+
+            let previous = pass.log_likelihood;
+            pass = fit.forward_backward(observations);
+            if (pass.log_likelihood - previous).abs() <= TOLERANCE * previous.abs().max(1.0) {
+                break;
+            }
+        }
+        fit.log_likelihood = pass.log_likelihood;
+        fit.smoothed = pass.smoothed;
+        fit.sort_by_mean();
+        Some(fit)
+    }
+
+    fn sort_by_mean(&mut self) {
+        let mut order: Vec<usize> = (0..self.states()).collect();
+        order.sort_by(|&a, &b| self.means[b].total_cmp(&self.means[a]));
+        self.means = order.iter().map(|&state| self.means[state]).collect();
+        self.stds = order.iter().map(|&state| self.stds[state]).collect();
+        self.initial = order.iter().map(|&state| self.initial[state]).collect();
+        self.transition = order
+            .iter()
+            .map(|&i| order.iter().map(|&j| self.transition[i][j]).collect())
+            .collect();
+        self.smoothed = self
+            .smoothed
+            .iter()
+            .map(|row| order.iter().map(|&state| row[state]).collect())
+            .collect();
+    }
+
+    /// Most probable state of each observation under the smoothed probabilities.
+    pub fn labels(&self) -> Vec<usize> {
+        self.smoothed
+            .iter()
+            .map(|row| {
+                (0..row.len())
+                    .max_by(|&a, &b| row[a].total_cmp(&row[b]))
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    pub fn summary(&self, frequency: &str, periods_per_year: f64) -> RegimeFitSummary {
+        RegimeFitSummary {
+            frequency: frequency.to_string(),
+            states: self
+                .means
+                .iter()
+                .zip(&self.stds)
+                .zip(self.stationary_distribution())
+                .map(|((mean, std), probability)| RegimeStateEstimate {
+                    mean: mean * periods_per_year,
+                    volatility: std * periods_per_year.sqrt(),
+                    stationary_probability: probability,
+                })
+                .collect(),
+            transition_matrix: self.transition.clone(),
+            smoothed_probabilities: self.smoothed.clone(),
+            log_likelihood: self.log_likelihood,
+            iterations: self.iterations,
+        }
+    }
+
+    pub fn stationary_distribution(&self) -> Vec<f64> {
+        stationary_distribution(&self.transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculations::RandomSource;
+    use crate::structs::RegimeStateInput;
+
+    fn state(name: &str, mean: f64) -> RegimeStateInput {
+        RegimeStateInput {
+            name: Some(name.to_string()),
+            mean,
+            std: 0.1,
+            inflation_mean: None,
+            inflation_variability: None,
+        }
+    }
+
+    #[test]
+    fn per_state_inputs_follow_an_unsorted_model() {
+        let input = RegimeModelInput {
+            stay_growth: 0.9,
+            stay_crisis: 0.9,
+            growth_mean: 0.0,
+            growth_std: 0.0,
+            crisis_mean: 0.0,
+            crisis_std: 0.0,
+            states: Some(vec![
+                state("normal", 0.06),
+                state("crisis", -0.2),
+                state("boom", 0.15),
+            ]),
+            transition_matrix: Some(vec![
+                vec![0.8, 0.15, 0.05],
+                vec![0.3, 0.6, 0.1],
+                vec![0.5, 0.1, 0.4],
+            ]),
+        };
+        let model = RegimeModel::from_input(&input);
+
+        let names: Vec<&str> = model.states.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["boom", "normal", "crisis"]);
+        assert_eq!(model.crisis_state(), 2);
+        // Boom -> crisis keeps its input probability after the reordering.
+        assert!((model.transition[0][2] - 0.1).abs() < 1e-12);
+        assert!((model.transition[1][0] - 0.05).abs() < 1e-12);
+
+        // An asset's regime means, given in input order, line up with the sorted states.
+        let asset_means = model.in_model_order(&[0.07, -0.3, 0.2]);
+        assert_eq!(asset_means, [Some(0.2), Some(0.07), Some(-0.3)]);
+        assert_eq!(model.in_model_order(&[0.07]), [None, Some(0.07), None]);
+    }
+
+    #[test]
+    fn default_inflation_means_keep_the_mixture_mean_with_three_states() {
+        let input = RegimeModelInput {
+            stay_growth: 0.9,
+            stay_crisis: 0.9,
+            growth_mean: 0.0,
+            growth_std: 0.0,
+            crisis_mean: 0.0,
+            crisis_std: 0.0,
+            states: Some(vec![
+                state("boom", 0.15),
+                state("normal", 0.06),
+                state("crisis", -0.2),
+            ]),
+            transition_matrix: Some(vec![
+                vec![0.7, 0.25, 0.05],
+                vec![0.1, 0.8, 0.1],
+                vec![0.2, 0.3, 0.5],
+            ]),
+        };
+        let model = RegimeModel::from_input(&input);
+        let stationary = model.stationary_distribution();
+        let means = model.default_inflation_means(0.03, 0.02, 0.015);
+
+        let mixture_mean: f64 = stationary.iter().zip(&means).map(|(p, m)| p * m).sum();
+        assert!((mixture_mean - 0.03).abs() < 1e-12);
+        assert!((means[2] - means[0] - 0.015).abs() < 1e-12);
+        assert_eq!(means[0], means[1]);
+
+        // A spread too wide for the variability is capped at 80% of it.
+        let capped = model.default_inflation_means(0.03, 0.001, 0.015);
+        let crisis_prob = stationary[2];
+        let spread = capped[2] - capped[0];
+        assert!((spread - 0.8 * 0.001 / (crisis_prob * (1.0 - crisis_prob)).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fit_recovers_a_synthetic_two_state_series() {
+        // (mean, std, stay probability) of the growth and crisis states.
+        let truth = [(0.01, 0.03, 0.95), (-0.02, 0.06, 0.85)];
+        let mut rng = RandomSource::new(Some(42.0));
+        let mut states = Vec::with_capacity(4000);
+        let mut state = 0;
+        let series: Vec<f64> = (0..4000)
+            .map(|_| {
+                if rng.random() > truth[state].2 {
+                    state = 1 - state;
+                }
+                states.push(state);
+                rng.normal(truth[state].0, truth[state].1)
+            })
+            .collect();
+        // Stay probabilities the hidden path actually realized.
+        let realized_stay = |state: usize| {
+            let (stays, visits) = states
+                .windows(2)
+                .filter(|pair| pair[0] == state)
+                .fold((0, 0), |(stays, visits), pair| {
+                    (stays + usize::from(pair[1] == state), visits + 1)
+                });
+            stays as f64 / visits as f64
+        };
+
+        let fit = RegimeFit::fit(&series, 2).unwrap();
+        for (state, &(mean, std, _)) in truth.iter().enumerate() {
+            assert!((fit.means[state] - mean).abs() < 0.005, "{:?}", fit.means);
+            assert!((fit.stds[state] - std).abs() < 0.005, "{:?}", fit.stds);
+            assert!(
+                (fit.transition[state][state] - realized_stay(state)).abs() < 0.02,
+                "{:?} vs {}",
+                fit.transition,
+                realized_stay(state)
+            );
+        }
+        assert_eq!(fit.smoothed.len(), series.len());
+    }
+
+    #[test]
+    fn fit_needs_enough_varying_observations() {
+        let series: Vec<f64> = (0..30).map(|t| (t % 7) as f64 * 0.01).collect();
+        assert!(RegimeFit::fit(&series, 2).is_some());
+        assert!(RegimeFit::fit(&series[..19], 2).is_none());
+        assert!(RegimeFit::fit(&series, 0).is_none());
+        assert!(RegimeFit::fit(&[0.01; 100], 2).is_none());
+    }
+}
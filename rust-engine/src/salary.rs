@@ -0,0 +1,178 @@
+use crate::accounts::{account_index, TAX_DEFERRED};
+use crate::calculations::RandomSource;
+use crate::structs::RetirementInput;
+
+const DEFAULT_REAL_GROWTH: f64 = 0.01;
+// Wage draws come from their own stream so stochastic salaries leave market paths unchanged.
+pub const WAGE_SEED_OFFSET: f64 = 104729.0;
+
+pub struct SalaryModel {
+    yearly_amount: f64,
+    real_growth: f64,
+    growth_variability: f64,
+    until_age: f64,
+    contribution_rate: f64,
+    employee_account: usize,
+    employer_account: usize,
+    match_rate: f64,
+    match_cap: f64,
+    // Real yearly limits on employee contributions per account.
+    annual_limits: [f64; 3],
+    // (from age, to age, work fraction).
+    career_breaks: Vec<(f64, f64, f64)>,
+    taxable: bool,
+}
+
+/// Monthly real salary amounts of one path.
+#[derive(Clone)]
+pub struct SalaryFlows {
+    pub gross: Vec<f64>,
+    // Gross salary less the employee's contributions.
+    pub take_home: Vec<f64>,
+    pub taxable: Vec<f64>,
+    pub employee: Vec<f64>,
+    pub employer: Vec<f64>,
+    // Employee and employer contributions by account.
+    pub by_account: [Vec<f64>; 3],
+}
+
+impl SalaryFlows {
+    pub fn empty(months: usize) -> Self {
+        Self {
+            gross: vec![0.0; months],
+            take_home: vec![0.0; months],
+            taxable: vec![0.0; months],
+            employee: vec![0.0; months],
+            employer: vec![0.0; months],
+            by_account: std::array::from_fn(|_| vec![0.0; months]),
+        }
+    }
+
+    pub fn contributions(&self, m: usize) -> f64 {
+        self.employee[m] + self.employer[m]
+    }
+
+    /// Stops the salary in month `m`.
+    pub fn clear_month(&mut self, m: usize) {
+        for series in [
+            &mut self.gross,
+            &mut self.take_home,
+            &mut self.taxable,
+            &mut self.employee,
+            &mut self.employer,
+        ] {
+            series[m] = 0.0;
+        }
+        for series in &mut self.by_account {
+            series[m] = 0.0;
+        }
+    }
+}
+
+impl SalaryModel {
+    pub fn from_input(input: &RetirementInput) -> Option<Self> {
+        let salary = input.salary.as_ref()?;
+        let account_or = |name: &Option<String>| {
+            name.as_deref()
+                .and_then(account_index)
+                .unwrap_or(TAX_DEFERRED)
+        };
+        let limits = salary.contribution_limits.as_ref();
+        let limit = |value: Option<f64>| value.map_or(f64::INFINITY, |limit| limit.max(0.0));
+
+        Some(Self {
+            yearly_amount: salary.yearly_amount.max(0.0),
+            real_growth: salary.real_growth.unwrap_or(DEFAULT_REAL_GROWTH),
+            growth_variability: salary.growth_variability.unwrap_or(0.0).max(0.0),
+            until_age: salary.until_age.unwrap_or(input.retirement_age),
+            contribution_rate: salary.contribution_rate.clamp(0.0, 1.0),
+            employee_account: account_or(&salary.contribution_account),
+            employer_account: account_or(&salary.employer_account),
+            match_rate: salary.employer_match_rate.unwrap_or(0.0).max(0.0),
+            // Without a cap the employer matches the whole employee contribution.
+            match_cap: salary.employer_match_cap.unwrap_or(1.0).clamp(0.0, 1.0),
+            annual_limits: [
+                limit(limits.and_then(|limits| limits.taxable)),
+                limit(limits.and_then(|limits| limits.tax_deferred)),
+                limit(limits.and_then(|limits| limits.tax_free)),
+            ],
+            career_breaks: salary
+                .career_breaks
+                .iter()
+                .flatten()
+                .map(|period| {
+                    (
+                        period.from_age,
+                        period.to_age,
+                        period.work_fraction.unwrap_or(0.0).clamp(0.0, 1.0),
+                    )
+                })
+                .collect(),
+            // `yearlyAmount` is gross pay, so it is taxed unless flagged otherwise.
+            taxable: salary.taxable.unwrap_or(true),
+        })
+    }
+
+    pub fn is_stochastic(&self) -> bool {
+        self.growth_variability > 0.0
+    }
+
+    fn work_fraction(&self, age: f64) -> f64 {
+        self.career_breaks
+            .iter()
+            .find(|&&(from_age, to_age, _)| from_age <= age && to_age > age)
+            .map_or(1.0, |&(_, _, fraction)| fraction)
+    }
+
+    /// One path's raise shocks, one per simulated year after the first.
+    pub fn draw_shocks(&self, months: usize, rng: &mut RandomSource) -> Vec<f64> {
+        (0..months.saturating_sub(1) / 12)
+            .map(|_| rng.normal(0.0, self.growth_variability))
+            .collect()
+    }
+
+    /// Monthly flows with a real raise at the start of each simulated year: the expected raise
+    /// plus that year's entry in `shocks` (none when empty). Contributions above an account's
+    /// yearly limit stay in take-home pay; the employer matches what was actually contributed.
+    pub fn flows(&self, current_age: f64, months: usize, shocks: &[f64]) -> SalaryFlows {
+        let mut flows = SalaryFlows::empty(months);
+        let mut level = 1.0;
+        let mut contributed_this_year = [0.0; 3];
+
+        for m in 0..months {
+            if m > 0 && m.is_multiple_of(12) {
+                let shock = shocks.get(m / 12 - 1).copied().unwrap_or(0.0);
+                level *= (1.0 + self.real_growth + shock).max(0.0);
+                contributed_this_year = [0.0; 3];
+            }
+            let age = current_age + m as f64 / 12.0;
+            if age >= self.until_age {
+                continue;
+            }
+
+            let gross = self.yearly_amount * level * self.work_fraction(age) / 12.0;
+            let room = (self.annual_limits[self.employee_account]
+                - contributed_this_year[self.employee_account])
+                .max(0.0);
+            let employee = (gross * self.contribution_rate).min(room);
+            contributed_this_year[self.employee_account] += employee;
+            let employer = self.match_rate * employee.min(gross * self.match_cap);
+
+            flows.gross[m] = gross;
+            flows.take_home[m] = gross - employee;
+            if self.taxable {
+                // Tax-deferred contributions come out of pay before tax.
+                flows.taxable[m] = if self.employee_account == TAX_DEFERRED {
+                    gross - employee
+                } else {
+                    gross
+                };
+            }
+            flows.employee[m] = employee;
+            flows.employer[m] = employer;
+            flows.by_account[self.employee_account][m] += employee;
+            flows.by_account[self.employer_account][m] += employer;
+        }
+        flows
+    }
+}
@@ -0,0 +1,548 @@
+use crate::calculations::RandomSource;
+use crate::engine::{SensitivityOutcome, SensitivityRow, SensitivityStats};
+use crate::simulation::run_monte_carlo_simulation;
+use crate::stats::income_sources_for_retirement_age;
+use crate::structs::{
+    AssetInput, HouseholdMember, IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod,
+};
+
+const DEFAULT_RELATIVE_CHANGE: f64 = 0.1;
+
+/// How far a parameter moves either way from its base value.
+#[derive(Clone, Copy)]
+enum Step {
+    // Scaled by 1 ± `relativeChange`: amounts and volatilities.
+    Relative,
+    // Moved by ± this much: ages, rates and shape parameters, which are often 0 or have no
+    // natural scale.
+    Absolute(f64),
+}
+
+impl Step {
+    fn apply(self, base_value: f64, direction: f64, relative_change: f64) -> f64 {
+        match self {
+            Step::Relative => base_value * (1.0 + direction * relative_change),
+            Step::Absolute(step) => base_value + direction * step,
+        }
+    }
+}
+
+const YEAR_STEP: Step = Step::Absolute(1.0);
+// Mean returns and inflation, the crisis spread and salary rates.
+const RATE_STEP: Step = Step::Absolute(0.01);
+const SHAPE_STEP: Step = Step::Absolute(0.5);
+// Correlations, persistence, probabilities and tax and match rates.
+const SHARE_STEP: Step = Step::Absolute(0.05);
+const FEE_STEP: Step = Step::Absolute(0.0025);
+
+// Name, accessor and step of a numeric field; the accessor gives `None` when an optional
+// field is unset.
+type Field<T> = (&'static str, fn(&mut T) -> Option<&mut f64>, Step);
+type InputAccessor = Box<dyn Fn(&mut RetirementInput) -> Option<&mut f64>>;
+
+const INPUT_FIELDS: [Field<RetirementInput>; 29] = [
+    // With `accounts` the account balances replace `currentSavings`.
+    (
+        "currentSavings",
+        |input| {
+            if input.accounts.is_none() {
+                Some(&mut input.current_savings)
+            } else {
+                None
+            }
+        },
+        Step::Relative,
+    ),
+    (
+        "accounts.taxable",
+        |input| Some(&mut input.accounts.as_mut()?.taxable.as_mut()?.balance),
+        Step::Relative,
+    ),
+    (
+        "accounts.taxDeferred",
+        |input| Some(&mut input.accounts.as_mut()?.tax_deferred.as_mut()?.balance),
+        Step::Relative,
+    ),
+    (
+        "accounts.taxFree",
+        |input| Some(&mut input.accounts.as_mut()?.tax_free.as_mut()?.balance),
+        Step::Relative,
+    ),
+    (
+        "meanReturn",
+        |input| Some(&mut input.mean_return),
+        RATE_STEP,
+    ),
+    (
+        "returnVariability",
+        |input| Some(&mut input.return_variability),
+        Step::Relative,
+    ),
+    (
+        "returnSkewness",
+        |input| Some(&mut input.return_skewness),
+        SHAPE_STEP,
+    ),
+    (
+        "returnKurtosis",
+        |input| Some(&mut input.return_kurtosis),
+        SHAPE_STEP,
+    ),
+    (
+        "equityBondCorrelation",
+        |input| Some(&mut input.equity_bond_correlation),
+        SHARE_STEP,
+    ),
+    (
+        "regimeModel.growthMean",
+        |input| Some(&mut input.regime_model.growth_mean),
+        RATE_STEP,
+    ),
+    (
+        "regimeModel.growthStd",
+        |input| Some(&mut input.regime_model.growth_std),
+        Step::Relative,
+    ),
+    (
+        "regimeModel.crisisMean",
+        |input| Some(&mut input.regime_model.crisis_mean),
+        RATE_STEP,
+    ),
+    (
+        "regimeModel.crisisStd",
+        |input| Some(&mut input.regime_model.crisis_std),
+        Step::Relative,
+    ),
+    (
+        "regimeModel.stayGrowth",
+        |input| Some(&mut input.regime_model.stay_growth),
+        SHARE_STEP,
+    ),
+    (
+        "regimeModel.stayCrisis",
+        |input| Some(&mut input.regime_model.stay_crisis),
+        SHARE_STEP,
+    ),
+    (
+        "inflationMean",
+        |input| Some(&mut input.inflation_mean),
+        RATE_STEP,
+    ),
+    (
+        "inflationVariability",
+        |input| Some(&mut input.inflation_variability),
+        Step::Relative,
+    ),
+    (
+        "inflationSkewness",
+        |input| Some(&mut input.inflation_skewness),
+        SHAPE_STEP,
+    ),
+    (
+        "inflationKurtosis",
+        |input| Some(&mut input.inflation_kurtosis),
+        SHAPE_STEP,
+    ),
+    (
+        "inflationCrisisSpread",
+        |input| input.inflation_crisis_spread.as_mut(),
+        RATE_STEP,
+    ),
+    (
+        "inflationPersistence",
+        |input| input.inflation_persistence.as_mut(),
+        SHARE_STEP,
+    ),
+    (
+        "annualFeePercent",
+        |input| Some(&mut input.annual_fee_percent),
+        FEE_STEP,
+    ),
+    (
+        "taxOnGainsPercent",
+        |input| Some(&mut input.tax_on_gains_percent),
+        SHARE_STEP,
+    ),
+    (
+        "safeWithdrawalRate",
+        |input| Some(&mut input.safe_withdrawal_rate),
+        FEE_STEP,
+    ),
+    (
+        "salary.yearlyAmount",
+        |input| Some(&mut input.salary.as_mut()?.yearly_amount),
+        Step::Relative,
+    ),
+    (
+        "salary.realGrowth",
+        |input| input.salary.as_mut()?.real_growth.as_mut(),
+        RATE_STEP,
+    ),
+    (
+        "salary.growthVariability",
+        |input| input.salary.as_mut()?.growth_variability.as_mut(),
+        Step::Relative,
+    ),
+    (
+        "salary.contributionRate",
+        |input| Some(&mut input.salary.as_mut()?.contribution_rate),
+        RATE_STEP,
+    ),
+    (
+        "salary.employerMatchRate",
+        |input| input.salary.as_mut()?.employer_match_rate.as_mut(),
+        SHARE_STEP,
+    ),
+];
+
+const ASSET_FIELDS: [Field<AssetInput>; 6] = [
+    ("meanReturn", |asset| asset.mean_return.as_mut(), RATE_STEP),
+    (
+        "returnVariability",
+        |asset| asset.return_variability.as_mut(),
+        Step::Relative,
+    ),
+    (
+        "returnSkewness",
+        |asset| asset.return_skewness.as_mut(),
+        SHAPE_STEP,
+    ),
+    (
+        "returnKurtosis",
+        |asset| asset.return_kurtosis.as_mut(),
+        SHAPE_STEP,
+    ),
+    (
+        "crisisMeanReturn",
+        |asset| asset.crisis_mean_return.as_mut(),
+        RATE_STEP,
+    ),
+    (
+        "crisisReturnVariability",
+        |asset| asset.crisis_return_variability.as_mut(),
+        Step::Relative,
+    ),
+];
+
+const MEMBER_FIELDS: [Field<HouseholdMember>; 2] = [
+    (
+        "currentAge",
+        |member| Some(&mut member.current_age),
+        YEAR_STEP,
+    ),
+    (
+        "retirementAge",
+        |member| Some(&mut member.retirement_age),
+        YEAR_STEP,
+    ),
+];
+
+fn input_accessor<F>(accessor: F) -> InputAccessor
+where
+    F: Fn(&mut RetirementInput) -> Option<&mut f64> + 'static,
+{
+    Box::new(accessor)
+}
+
+/// One full simulation's inputs.
+#[derive(Clone)]
+struct Scenario {
+    input: RetirementInput,
+    spending_periods: Vec<SpendingPeriod>,
+    income_sources: Vec<IncomeSource>,
+    lump_sum_events: Vec<LumpSumEvent>,
+    months: u32,
+}
+
+// Where a perturbed number lives in a scenario.
+enum Target {
+    // Moves the horizon with it.
+    CurrentAge,
+    SimulateUntilAge,
+    // Moves the income sources linked to the retirement age with it.
+    RetirementAge,
+    Input(InputAccessor),
+    Spending(usize),
+    Income(usize),
+    LumpSum(usize),
+}
+
+struct Parameter {
+    name: String,
+    base_value: f64,
+    step: Step,
+    target: Target,
+}
+
+// Months of a horizon moved by `years`.
+fn shift_months(months: u32, years: f64) -> u32 {
+    (months as f64 + years * 12.0).round().max(1.0) as u32
+}
+
+impl Scenario {
+    fn set(&mut self, target: &Target, value: f64) {
+        match target {
+            Target::CurrentAge => {
+                self.months = shift_months(self.months, self.input.current_age - value);
+                self.input.current_age = value;
+            }
+            Target::SimulateUntilAge => {
+                self.months = shift_months(self.months, value - self.input.simulate_until_age);
+                self.input.simulate_until_age = value;
+            }
+            Target::RetirementAge => {
+                self.income_sources = income_sources_for_retirement_age(
+                    &self.income_sources,
+                    self.input.retirement_age,
+                    value,
+                );
+                self.input.retirement_age = value;
+            }
+            Target::Input(field) => {
+                if let Some(field) = field(&mut self.input) {
+                    *field = value;
+                }
+            }
+            Target::Spending(index) => self.spending_periods[*index].yearly_amount = value,
+            Target::Income(index) => self.income_sources[*index].yearly_amount = value,
+            Target::LumpSum(index) => self.lump_sum_events[*index].amount = value,
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let ages = [
+            ("currentAge", self.input.current_age, Target::CurrentAge),
+            (
+                "retirementAge",
+                self.input.retirement_age,
+                Target::RetirementAge,
+            ),
+            (
+                "simulateUntilAge",
+                self.input.simulate_until_age,
+                Target::SimulateUntilAge,
+            ),
+        ]
+        .into_iter()
+        .map(|(name, base_value, target)| Parameter {
+            name: name.to_string(),
+            base_value,
+            step: YEAR_STEP,
+            target,
+        });
+
+        let mut accessors: Vec<(String, InputAccessor, Step)> = INPUT_FIELDS
+            .iter()
+            .map(|&(name, field, step)| (name.to_string(), input_accessor(field), step))
+            .collect();
+        let assets = self
+            .input
+            .portfolio
+            .as_ref()
+            .map_or(&[][..], |portfolio| portfolio.assets.as_slice());
+        for (index, asset) in assets.iter().enumerate() {
+            accessors.extend(ASSET_FIELDS.iter().map(|&(name, field, step)| {
+                let accessor = input_accessor(move |input| {
+                    field(input.portfolio.as_mut()?.assets.get_mut(index)?)
+                });
+                (format!("asset:{}.{}", asset.id, name), accessor, step)
+            }));
+        }
+        let members = self
+            .input
+            .household
+            .as_ref()
+            .map_or(&[][..], |household| household.members.as_slice());
+        for (index, member) in members.iter().enumerate() {
+            accessors.extend(MEMBER_FIELDS.iter().map(|&(name, field, step)| {
+                let accessor = input_accessor(move |input| {
+                    field(input.household.as_mut()?.members.get_mut(index)?)
+                });
+                (format!("member:{}.{}", member.id, name), accessor, step)
+            }));
+        }
+        let mut input = self.input.clone();
+        let fields = accessors.into_iter().filter_map(|(name, accessor, step)| {
+            let base_value = *accessor(&mut input)?;
+            Some(Parameter {
+                name,
+                base_value,
+                step,
+                target: Target::Input(accessor),
+            })
+        });
+
+        let spending = self
+            .spending_periods
+            .iter()
+            .enumerate()
+            .map(|(index, period)| Parameter {
+                name: format!("spending:{}", period.id),
+                base_value: period.yearly_amount,
+                step: Step::Relative,
+                target: Target::Spending(index),
+            });
+        let income = self
+            .income_sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| Parameter {
+                name: format!("income:{}", source.id),
+                base_value: source.yearly_amount,
+                step: Step::Relative,
+                target: Target::Income(index),
+            });
+        let lump_sums = self
+            .lump_sum_events
+            .iter()
+            .enumerate()
+            .map(|(index, event)| Parameter {
+                name: format!("lumpSum:{}", event.id),
+                base_value: event.amount,
+                step: Step::Relative,
+                target: Target::LumpSum(index),
+            });
+        ages.chain(fields)
+            .chain(spending)
+            .chain(income)
+            .chain(lump_sums)
+            .collect()
+    }
+}
+
+fn run_scenario(scenario: &Scenario) -> Result<SensitivityOutcome, String> {
+    let input = &scenario.input;
+    let months = scenario.months;
+    let retire_month = ((input.retirement_age - input.current_age) * 12.0)
+        .round()
+        .clamp(0.0, months as f64) as u32;
+    let result = run_monte_carlo_simulation(
+        input,
+        &scenario.spending_periods,
+        &scenario.income_sources,
+        &scenario.lump_sum_events,
+        months,
+        retire_month,
+        None,
+    )?;
+    Ok(SensitivityOutcome {
+        success_probability: result.stats.success_probability,
+        median_terminal_wealth: result.stats.final_median,
+        fi_target: result.stats.fi_target,
+    })
+}
+
+fn difference(outcome: &SensitivityOutcome, base: &SensitivityOutcome) -> SensitivityOutcome {
+    SensitivityOutcome {
+        success_probability: outcome.success_probability - base.success_probability,
+        median_terminal_wealth: outcome.median_terminal_wealth - base.median_terminal_wealth,
+        fi_target: outcome.fi_target - base.fi_target,
+    }
+}
+
+/// Reruns the simulation with each numeric input field and each cash flow moved either way by
+/// its step (amounts by 1 ± `relativeChange`, ages and rates by a fixed amount) and ranks them
+/// by the swing in success probability. Every run shares
+/// one seed, so the differences come from the parameter rather than from sampling noise.
+pub fn run_sensitivity_analysis(
+    input: &RetirementInput,
+    spending_periods: &[SpendingPeriod],
+    income_sources: &[IncomeSource],
+    lump_sum_events: &[LumpSumEvent],
+    months: u32,
+    progress_callback: Option<&dyn Fn(f64)>,
+) -> Result<SensitivityStats, String> {
+    let options = input.sensitivity.as_ref();
+    let relative_change = options
+        .and_then(|options| options.relative_change)
+        .unwrap_or(DEFAULT_RELATIVE_CHANGE)
+        .abs();
+
+    let mut base_input = input.clone();
+    base_input.seed = Some(
+        input
+            .seed
+            .unwrap_or_else(|| (RandomSource::new(None).random() * 4294967296.0).floor()),
+    );
+    if let Some(simulations) = options.and_then(|options| options.simulations) {
+        base_input.simulations = simulations;
+    }
+    // Side analyses do not feed the compared outcomes, and a fixed path count keeps the runs
+    // on common random numbers.
+    base_input.solver = None;
+    base_input.stress_test = None;
+    base_input.conversion_optimizer = None;
+    base_input.convergence = None;
+    let base_scenario = Scenario {
+        input: base_input,
+        spending_periods: spending_periods.to_vec(),
+        income_sources: income_sources.to_vec(),
+        lump_sum_events: lump_sum_events.to_vec(),
+        months,
+    };
+
+    let parameters: Vec<Parameter> = base_scenario
+        .parameters()
+        .into_iter()
+        // Scaling a zero leaves it unchanged.
+        .filter(|parameter| {
+            !matches!(parameter.step, Step::Relative) || parameter.base_value != 0.0
+        })
+        .filter(|parameter| {
+            options
+                .and_then(|options| options.parameters.as_ref())
+                .is_none_or(|names| names.contains(&parameter.name))
+        })
+        .collect();
+    let run_count = 1 + 2 * parameters.len();
+    let report = |done: usize| {
+        if let Some(cb) = progress_callback {
+            cb(done as f64 / run_count as f64);
+        }
+    };
+
+    let base = run_scenario(&base_scenario)?;
+    report(1);
+    let mut rows: Vec<SensitivityRow> = parameters
+        .iter()
+        .enumerate()
+        .map(|(index, parameter)| {
+            let [low, high] = [-1.0, 1.0].map(|direction| {
+                let value = parameter
+                    .step
+                    .apply(parameter.base_value, direction, relative_change);
+                let mut scenario = base_scenario.clone();
+                scenario.set(&parameter.target, value);
+                run_scenario(&scenario).map(|outcome| (value, difference(&outcome, &base)))
+            });
+            let (low, high) = (low?, high?);
+            report(1 + 2 * (index + 1));
+            Ok(SensitivityRow {
+                parameter: parameter.name.clone(),
+                base_value: parameter.base_value,
+                low_value: low.0,
+                high_value: high.0,
+                success_probability_swing: (high.1.success_probability - low.1.success_probability)
+                    .abs(),
+                low: low.1,
+                high: high.1,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    rows.sort_by(|a, b| {
+        b.success_probability_swing
+            .total_cmp(&a.success_probability_swing)
+            .then_with(|| {
+                let wealth_swing = |row: &SensitivityRow| {
+                    (row.high.median_terminal_wealth - row.low.median_terminal_wealth).abs()
+                };
+                wealth_swing(b).total_cmp(&wealth_swing(a))
+            })
+    });
+
+    Ok(SensitivityStats {
+        relative_change,
+        base,
+        rows,
+    })
+}
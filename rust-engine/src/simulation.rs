@@ -1,19 +1,41 @@
-use crate::calculations::{percentile, summarize, PercentileSeries, RandomSource};
+use crate::accounts::{starting_balance, AccountModel, TAXABLE, TAX_DEFERRED, TAX_FREE};
+use crate::assets::{
+    compare_glide_paths, compare_rebalancing_policies, AllocationCandidate, GlidePath, Portfolio,
+    PortfolioState, RebalancePolicy, MIN_HISTORY_MONTHS,
+};
+use crate::backtest::{build_cohort, cohort_count, month_label};
+use crate::bootstrap::{BlockSampler, BootstrapScheme};
+use crate::calculations::{percentile_bands, summarize, RandomSource};
+use crate::claiming::analyze_claiming_ages;
+use crate::convergence::{build_convergence_stats, host_clock, AdaptiveSampling};
+use crate::conversion::optimize_conversions;
 use crate::engine::{
     annual_to_monthly_return, clamp_annual_return, clamp_monthly_return,
     draw_monthly_return_shaped, draw_student_t, initial_regime_state,
-    student_t_degrees_from_kurtosis, transition_regime_state, ReturnMoments, SimulationResult,
-    SummaryStats,
+    student_t_degrees_from_kurtosis, transition_regime_state, AccountPercentiles,
+    AssetWeightPercentiles, InflationPersistence, RebalancingStats, ReturnMoments, SalaryStats,
+    SimulationResult, SpendingStats, SummaryStats, FI_TARGET_SUCCESS_PROBABILITY,
 };
 use crate::engine2::{
     apply_moment_targeting, bootstrap_indices_by_regime_monthly, bootstrap_pool_by_regime,
-    build_cashflow_arrays, detect_regimes, detect_regimes_monthly,
-    estimate_markov_stay_probabilities, monthly_returns_to_annual_series, spending_at_age,
+    build_cashflow_arrays, detect_regimes, estimate_markov_transition_matrix, household_members,
+    monthly_returns_to_annual_series, spending_at_age, RealizedFlows, RegimePools,
+};
+use crate::mortality::{LifeTable, MAX_AGE, MORTALITY_SEED_OFFSET};
+use crate::regimes::RegimeModel;
+use crate::replay::{
+    AllocationPath, AllocationReplay, PathReplay, PlanInputs, PlanRules, PlanState,
 };
+use crate::salary::{SalaryModel, WAGE_SEED_OFFSET};
+use crate::solver::{run_solvers, SolverReplay};
 use crate::stats::{
-    build_ruin_surface, build_sequence_risk_summary, find_retirement_balance_target,
+    build_mortality_stats, build_ruin_surface, build_sequence_risk_summary, build_spending_stats,
+    find_retirement_balance_target, perfect_withdrawal_rate,
 };
+use crate::stress::{run_stress_tests, StressReplay};
 use crate::structs::{IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod};
+use crate::tax::TaxSchedule;
+use crate::withdrawal::WithdrawalPolicy;
 use std::f64;
 
 fn build_bootstrap_history(
@@ -21,24 +43,35 @@ fn build_bootstrap_history(
     rng: &mut RandomSource,
     years: u32,
 ) -> Vec<f64> {
-    let stay_growth = crate::engine::clamp_transition_probability(input.regime_model.stay_growth);
-    let stay_crisis = crate::engine::clamp_transition_probability(input.regime_model.stay_crisis);
-    let growth_mean = input.regime_model.growth_mean;
-    let growth_std = input.regime_model.growth_std.max(0.01);
-    let crisis_mean = input.regime_model.crisis_mean;
-    let crisis_std = input.regime_model.crisis_std.max(growth_std + 0.01);
+    let model = RegimeModel::from_input(&input.regime_model);
+    let growth_std = model.states[0].std.max(0.01);
+    // The crisis state is kept more volatile than the growth state.
+    let stds: Vec<f64> = model
+        .states
+        .iter()
+        .enumerate()
+        .map(|(state, regime)| {
+            if state == 0 {
+                growth_std
+            } else if state == model.crisis_state() {
+                regime.std.max(growth_std + 0.01)
+            } else {
+                regime.std.max(0.01)
+            }
+        })
+        .collect();
     let return_df = student_t_degrees_from_kurtosis(input.return_kurtosis);
     let skew_shift = input.return_skewness.clamp(-2.0, 2.0) * 0.12;
 
     let mut series = Vec::with_capacity(years as usize);
-    let mut state = initial_regime_state(stay_growth, stay_crisis, rng);
+    let mut state = initial_regime_state(&model.transition, rng);
 
     for year in 0..years {
         if year > 0 {
-            state = transition_regime_state(state, stay_growth, stay_crisis, rng);
+            state = transition_regime_state(state, &model.transition, rng);
         }
-        let mean = if state == 0 { growth_mean } else { crisis_mean };
-        let std = if state == 0 { growth_std } else { crisis_std };
+        let mean = model.states[state].mean;
+        let std = stds[state];
         let annual_return =
             clamp_annual_return(mean + std * (draw_student_t(return_df, rng) + skew_shift));
         series.push(annual_return);
@@ -93,6 +126,7 @@ fn summarize_return_moments(values: &[f64]) -> ReturnMoments {
 pub struct SimulationResultWrapper {
     pub simulation: SimulationResult,
     pub stats: SummaryStats,
+    pub spending_stats: Option<SpendingStats>,
     pub sim_count: u32,
 }
 
@@ -104,11 +138,12 @@ pub fn run_monte_carlo_simulation(
     months: u32,
     retire_month: u32,
     progress_callback: Option<&dyn Fn(f64)>,
-) -> SimulationResultWrapper {
+) -> Result<SimulationResultWrapper, String> {
     let mut rng = RandomSource::new(input.seed);
 
     let simulation_mode = input.simulation_mode.as_deref().unwrap_or("historical");
-    let use_historical_bootstrap = simulation_mode == "historical";
+    let bootstrap_scheme = BootstrapScheme::from_mode(simulation_mode);
+    let use_historical_bootstrap = bootstrap_scheme.is_some();
 
     let target_annual_mean = input.mean_return;
     let target_annual_std = input.return_variability.max(0.0);
@@ -122,15 +157,57 @@ pub fn run_monte_carlo_simulation(
         lump_sum_events,
         months,
     );
-    let monthly_net_flow = arrays.monthly_net_flow;
-    let lump_sum_by_month = arrays.lump_sum_by_month;
-
-    let stay_growth = crate::engine::clamp_transition_probability(input.regime_model.stay_growth);
-    let stay_crisis = crate::engine::clamp_transition_probability(input.regime_model.stay_crisis);
-    let growth_mean = input.regime_model.growth_mean;
-    let growth_std = input.regime_model.growth_std.max(0.0);
-    let crisis_mean = input.regime_model.crisis_mean;
-    let crisis_std = input.regime_model.crisis_std.max(0.0);
+    let withdrawal_policy = WithdrawalPolicy::from_input(input);
+    let account_model = AccountModel::from_input(input);
+    let tax_schedule = input.tax_schedule.as_ref().map(TaxSchedule::from_input);
+    let portfolio = Portfolio::from_input(input);
+    let use_asset_history =
+        use_historical_bootstrap && portfolio.as_ref().is_some_and(Portfolio::has_history);
+    let portfolio_input = input.portfolio.as_ref();
+    let glide_path = portfolio
+        .as_ref()
+        .zip(portfolio_input.and_then(|portfolio| portfolio.glide_path.as_ref()))
+        .map(|(portfolio, glide_path)| {
+            GlidePath::from_input(glide_path, input.retirement_age, portfolio.equity_weight())
+        });
+    let targets_by_month = portfolio.as_ref().map(|portfolio| {
+        portfolio.targets_by_month(glide_path.as_ref(), input.current_age, months as usize)
+    });
+    let rebalancing_input = portfolio_input.and_then(|portfolio| portfolio.rebalancing.as_ref());
+    let rebalance_policy = RebalancePolicy::from_input(rebalancing_input)?;
+    let household_members = household_members(input);
+    let life_table = input
+        .mortality
+        .as_ref()
+        .filter(|_| household_members.is_empty())
+        .map(|mortality| LifeTable::from_input(mortality, input.current_age))
+        .transpose()?;
+    let member_life_tables: Vec<Option<LifeTable>> = household_members
+        .iter()
+        .map(|member| {
+            member
+                .mortality
+                .as_ref()
+                .or(input.mortality.as_ref())
+                .map(|mortality| LifeTable::from_input(mortality, member.current_age))
+                .transpose()
+        })
+        .collect::<Result<_, _>>()?;
+    let mortality_enabled = life_table.is_some() || member_life_tables.iter().any(Option::is_some);
+    // Under `successDefinition: "mortality"` a path succeeds unless it runs out while the
+    // household is alive.
+    let mortality_success = mortality_enabled
+        && input
+            .mortality
+            .as_ref()
+            .and_then(|mortality| mortality.success_definition.as_deref())
+            == Some("mortality");
+    let mut mortality_rng = RandomSource::new(input.seed.map(|seed| seed + MORTALITY_SEED_OFFSET));
+    let salary_model = SalaryModel::from_input(input);
+    let mut wage_rng = RandomSource::new(input.seed.map(|seed| seed + WAGE_SEED_OFFSET));
+
+    let regime_model = RegimeModel::from_input(&input.regime_model);
+    let regime_count = regime_model.len();
 
     let mut bootstrap_history = vec![];
     if use_historical_bootstrap {
@@ -177,16 +254,30 @@ pub fn run_monte_carlo_simulation(
         bootstrap_history
     };
 
-    let monthly_history = if use_historical_bootstrap {
-        input.historical_monthly_returns.clone().unwrap_or_default()
+    // Each kept month carries its position in the input series, so the inflation series stays
+    // aligned with the returns when non-finite months are dropped.
+    let (monthly_history, history_months): (Vec<f64>, Vec<usize>) = if use_asset_history {
+        portfolio
+            .as_ref()
+            .map(|portfolio| {
+                (
+                    portfolio.blended_history(),
+                    portfolio.history_months().to_vec(),
+                )
+            })
+            .unwrap_or_default()
+    } else if use_historical_bootstrap {
+        input
+            .historical_monthly_returns
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter(|(_, v)| v.is_finite())
+            .map(|(month, &v)| (clamp_monthly_return(v), month))
+            .unzip()
     } else {
-        vec![]
+        (vec![], vec![])
     };
-    let monthly_history: Vec<f64> = monthly_history
-        .into_iter()
-        .filter(|v| v.is_finite())
-        .map(clamp_monthly_return)
-        .collect();
 
     let monthly_history_len = monthly_history.len().max(1) as f64;
     let monthly_history_mean = monthly_history.iter().sum::<f64>() / monthly_history_len;
@@ -201,7 +292,8 @@ pub fn run_monte_carlo_simulation(
         monthly_history
             .iter()
             .map(|&v| {
-                let val = if moment_targeting {
+                // Asset histories are moment-targeted one by one.
+                let val = if moment_targeting && !use_asset_history {
                     apply_moment_targeting(
                         v,
                         monthly_history_mean,
@@ -219,34 +311,62 @@ pub fn run_monte_carlo_simulation(
         monthly_history
     };
 
-    let use_monthly_calibration = effective_monthly_history.len() >= 120;
-
-    let annual_detected_regimes = detect_regimes(&effective_annual_history);
-    let annual_regime_bootstrap_pool =
-        bootstrap_pool_by_regime(&effective_annual_history, &annual_detected_regimes);
+    let use_monthly_calibration = effective_monthly_history.len() >= MIN_HISTORY_MONTHS;
+    // Schemes without regime conditioning take the regime of the sampled month, so inflation
+    // still follows the market state.
+    let regime_from_history = use_monthly_calibration
+        && bootstrap_scheme.is_some_and(|scheme| !scheme.conditions_on_regime());
+    // The sequential backtest runs one path per historical start month.
+    let sequential =
+        use_monthly_calibration && bootstrap_scheme == Some(BootstrapScheme::Sequential);
+    // A supplied inflation series is resampled with the returns, month for month.
+    let historical_inflation: Vec<f64> = input
+        .historical_monthly_inflation
+        .as_deref()
+        .filter(|_| use_monthly_calibration)
+        .map(|inflation| {
+            history_months
+                .iter()
+                .map(|&month| inflation.get(month).copied().unwrap_or(f64::NAN))
+                .collect()
+        })
+        .unwrap_or_default();
+    let inflation_persistence = input.inflation_persistence.unwrap_or(0.0);
+
+    let (annual_regime_fit, _) = detect_regimes(&effective_annual_history, regime_count);
+    let annual_regime_bootstrap_pool = bootstrap_pool_by_regime(
+        &effective_annual_history,
+        regime_count,
+        annual_regime_fit.as_ref(),
+    );
 
-    let monthly_detected_regimes = if use_monthly_calibration {
-        detect_regimes_monthly(&effective_monthly_history)
+    let (monthly_regime_fit, monthly_detected_regimes) = if use_monthly_calibration {
+        detect_regimes(&effective_monthly_history, regime_count)
     } else {
-        vec![]
+        (None, vec![])
     };
 
     let monthly_regime_bootstrap_indices = if use_monthly_calibration {
-        bootstrap_indices_by_regime_monthly(&effective_monthly_history, &monthly_detected_regimes)
+        bootstrap_indices_by_regime_monthly(
+            &effective_monthly_history,
+            regime_count,
+            monthly_regime_fit.as_ref(),
+        )
     } else {
-        crate::engine2::RegimePools {
-            growth: vec![],
-            crisis: vec![],
-        }
+        RegimePools::empty(regime_count)
     };
 
-    let monthly_markov = if use_monthly_calibration {
-        estimate_markov_stay_probabilities(&monthly_detected_regimes)
+    let monthly_transition = if use_monthly_calibration {
+        estimate_markov_transition_matrix(monthly_regime_fit.as_ref())
     } else {
-        (
-            crate::engine::clamp_transition_probability(stay_growth.powf(1.0 / 12.0)),
-            crate::engine::clamp_transition_probability(stay_crisis.powf(1.0 / 12.0)),
-        )
+        None
+    }
+    .unwrap_or_else(|| regime_model.monthly_transition());
+
+    let regime_fit = if use_monthly_calibration {
+        monthly_regime_fit.map(|fit| fit.summary("monthly", 12.0))
+    } else {
+        annual_regime_fit.map(|fit| fit.summary("annual", 1.0))
     };
 
     let converted_monthly;
@@ -256,7 +376,11 @@ pub fn run_monte_carlo_simulation(
     } else {
         &effective_annual_history
     });
-    let sim_count = 400.max(input.simulations.round() as usize);
+    let sim_count = if sequential {
+        cohort_count(effective_monthly_history.len(), months as usize)
+    } else {
+        400.max(input.simulations.round() as usize)
+    };
     let mut all_balances: Vec<Vec<f64>> = Vec::with_capacity(sim_count);
     let mut final_balances = Vec::with_capacity(sim_count);
     let mut retire_balances = Vec::with_capacity(sim_count);
@@ -265,21 +389,162 @@ pub fn run_monte_carlo_simulation(
     let mut depleted_flags = Vec::with_capacity(sim_count);
     let mut annual_real_returns_by_sim = Vec::with_capacity(sim_count);
     let mut growth_factors: Vec<Vec<f64>> = Vec::with_capacity(sim_count);
+    let mut perfect_withdrawal_rates = Vec::with_capacity(sim_count);
+    let mut pwr_depleted_paths = 0;
+    let pwr_bequest = input.pwr_bequest.unwrap_or(0.0).max(0.0);
+    let mut inflation_paths: Vec<Vec<f64>> = Vec::with_capacity(sim_count);
+    let mut all_spending: Vec<Vec<f64>> = Vec::new();
+    let mut account_balances_by_sim: [Vec<Vec<f64>>; 3] = Default::default();
+    let mut lifetime_taxes = Vec::new();
+    let asset_count = portfolio
+        .as_ref()
+        .map_or(0, |portfolio| portfolio.assets.len());
+    let mut asset_weights_by_sim: Vec<Vec<Vec<f64>>> = vec![Vec::new(); asset_count];
+    let mut annual_turnover: Vec<f64> = Vec::new();
+    let mut death_ages: Vec<f64> = Vec::new();
+    let mut depletion_months: Vec<Option<usize>> = Vec::with_capacity(sim_count);
+    let mut member_death_months_by_sim: Vec<Vec<Option<usize>>> = Vec::with_capacity(sim_count);
+    let mut realized_spending_by_sim: Vec<Vec<f64>> = Vec::new();
+    let mut planned_spending_by_sim: Vec<Vec<f64>> = Vec::new();
+    let mut historical_cohorts = Vec::new();
+    let nominal_output = input.nominal_output.unwrap_or(false);
+    // Final yearly salary, lifetime salary, employee and employer contributions, then lifetime
+    // contributions into each account, per path.
+    let mut salary_totals: [Vec<f64>; 7] = Default::default();
+    let mut nominal_balances_by_sim: Vec<Vec<f64>> = Vec::new();
     let mut success_count = 0;
 
     let spending_at_retirement = spending_at_age(input.retirement_age, spending_periods, 1.0);
 
-    let growth_prob = crate::engine::get_growth_stationary_probability(stay_growth, stay_crisis);
-    let crisis_prob = 1.0 - growth_prob;
-    let requested_inflation_spread = input.inflation_crisis_spread.unwrap_or(0.015);
-    let max_inflation_spread =
-        (input.inflation_variability.powi(2) / (growth_prob * crisis_prob)).sqrt();
-    let effective_inflation_spread = requested_inflation_spread.min(max_inflation_spread * 0.8);
-    let growth_inflation_mean = input.inflation_mean - crisis_prob * effective_inflation_spread;
-    let crisis_inflation_mean = input.inflation_mean + growth_prob * effective_inflation_spread;
+    let default_inflation_means = regime_model.default_inflation_means(
+        input.inflation_mean,
+        input.inflation_variability,
+        input.inflation_crisis_spread.unwrap_or(0.015),
+    );
+    // (mean, variability) of annual inflation per regime state. Without explicit values the
+    // crisis state carries the spread and every other state sits below the mean.
+    let regime_inflation: Vec<(f64, f64)> = regime_model
+        .states
+        .iter()
+        .zip(&default_inflation_means)
+        .map(|(regime, &default_mean)| {
+            (
+                regime.inflation_mean.unwrap_or(default_mean),
+                regime
+                    .inflation_variability
+                    .unwrap_or(input.inflation_variability),
+            )
+        })
+        .collect();
+    // (drift, noise) added to annual bootstrap draws per regime state.
+    let regime_stress: Vec<(f64, f64)> = regime_model
+        .states
+        .iter()
+        .enumerate()
+        .map(|(state, regime)| {
+            if state == 0 {
+                (0.0, regime.std.max(0.0) * 0.04)
+            } else {
+                (
+                    (regime.mean - regime_model.states[0].mean) * 0.1,
+                    regime.std.max(0.0) * 0.08,
+                )
+            }
+        })
+        .collect();
 
     let block_length = input.block_length.unwrap_or(6);
 
+    let mut rebalancing_candidates: Vec<AllocationCandidate> = targets_by_month
+        .as_ref()
+        .map(|targets| {
+            RebalancePolicy::all(rebalancing_input)
+                .into_iter()
+                .map(|policy| AllocationCandidate::new(policy, targets.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut glide_path_candidates: Option<Vec<(GlidePath, AllocationCandidate)>> = portfolio
+        .as_ref()
+        .zip(portfolio_input)
+        .filter(|(_, portfolio_input)| {
+            portfolio_input.glide_path.is_some() || portfolio_input.glide_path_comparison.is_some()
+        })
+        .map(|(portfolio, portfolio_input)| {
+            let static_equity = portfolio.equity_weight();
+            let glide_paths: Vec<GlidePath> = match &portfolio_input.glide_path_comparison {
+                Some(candidates) => candidates
+                    .iter()
+                    .map(|candidate| {
+                        GlidePath::from_input(candidate, input.retirement_age, static_equity)
+                    })
+                    .collect(),
+                None => GlidePath::defaults(input.retirement_age, static_equity)
+                    .into_iter()
+                    .chain(portfolio_input.glide_path.as_ref().map(|glide_path| {
+                        GlidePath::from_input(glide_path, input.retirement_age, static_equity)
+                    }))
+                    .collect(),
+            };
+            glide_paths
+                .into_iter()
+                .map(|glide_path| {
+                    let targets = portfolio.targets_by_month(
+                        Some(&glide_path),
+                        input.current_age,
+                        months as usize,
+                    );
+                    (
+                        glide_path,
+                        AllocationCandidate::new(rebalance_policy, targets),
+                    )
+                })
+                .collect()
+        });
+
+    // The cash-flow side of each month, shared with the replays of candidate plans.
+    let plan = PlanInputs {
+        input,
+        spending_periods,
+        income_sources,
+        lump_sum_events,
+        months,
+    };
+    let rules = PlanRules {
+        account_model: account_model.as_ref(),
+        tax_schedule: tax_schedule.as_ref(),
+        withdrawal_policy: withdrawal_policy.as_ref(),
+        retire_month: retire_month as usize,
+        current_age: input.current_age,
+        spending_scale: 1.0,
+    };
+    // Rebalancing policies and glide paths are compared on each path's asset returns as they
+    // are drawn.
+    let allocation_replay = AllocationReplay {
+        input,
+        rules,
+        monthly_fee_factor: (1.0 - input.annual_fee_percent.clamp(0.0, 1.0) / 12.0).max(0.0),
+        tax_on_gains_rate: if account_model.is_some() {
+            0.0
+        } else {
+            input.tax_on_gains_percent.clamp(0.0, 1.0)
+        },
+    };
+    // Rebalancing sales realize taxable gains only with accounts; replays need them per month.
+    let record_sales = account_model.is_some() && targets_by_month.is_some();
+    let mut rebalancing_sales_by_sim: Vec<Vec<f64>> = Vec::new();
+    let mut wage_shocks_by_sim: Vec<Vec<f64>> = Vec::with_capacity(sim_count);
+
+    let adaptive_sampling = (!sequential)
+        .then(|| AdaptiveSampling::from_input(input.convergence.as_ref(), sim_count, host_clock))
+        .flatten();
+    let max_sim_count = adaptive_sampling
+        .as_ref()
+        .map_or(sim_count, AdaptiveSampling::max_paths);
+    let sampling_started = adaptive_sampling
+        .as_ref()
+        .and_then(AdaptiveSampling::started);
+    let mut stopped_by = None;
     let progress_step = (sim_count / 10).max(1);
 
     // Signal that setup is complete, simulation is starting
@@ -287,29 +552,119 @@ pub fn run_monte_carlo_simulation(
         cb(0.0);
     }
 
-    for sim in 0..sim_count {
-        let mut balance = input.current_savings;
+    for sim in 0..max_sim_count {
+        let mut state = PlanState::new(input);
+        let mut sim_accounts: [Vec<f64>; 3] = if account_model.is_some() {
+            std::array::from_fn(|_| vec![0.0_f64; months as usize])
+        } else {
+            Default::default()
+        };
         let mut depleted = false;
         let mut cumulative_shortfall = 0.0;
         let mut depleted_months = 0;
+        let mut first_depletion_month = None;
+        let mut member_death_months = Vec::with_capacity(household_members.len());
+        if let Some(table) = &life_table {
+            death_ages.push(table.draw_death_age(input.current_age, &mut mortality_rng));
+        } else if mortality_enabled {
+            // The household's death age is the last survivor's, on the primary age axis.
+            let mut household_death_age = input.current_age;
+            for (member, table) in household_members.iter().zip(&member_life_tables) {
+                let member_death_age = table.as_ref().map_or(MAX_AGE as f64, |table| {
+                    table.draw_death_age(member.current_age, &mut mortality_rng)
+                });
+                let death_month = ((member_death_age - member.current_age) * 12.0)
+                    .max(0.0)
+                    .floor() as usize;
+                member_death_months.push(Some(death_month).filter(|&d| d < months as usize));
+                household_death_age = household_death_age
+                    .max(input.current_age + member_death_age - member.current_age);
+            }
+            death_ages.push(household_death_age);
+        }
+        let wage_shocks = salary_model
+            .as_ref()
+            .filter(|model| model.is_stochastic())
+            .map(|model| model.draw_shocks(months as usize, &mut wage_rng))
+            .unwrap_or_default();
+        let path_flows = plan.path_flows(&arrays, &member_death_months, &wage_shocks);
+        let planned_flows = path_flows.as_ref().unwrap_or(&arrays);
+        if let Some(salary) = &planned_flows.salary {
+            let lifetime = |series: &[f64]| series.iter().sum::<f64>();
+            salary_totals[0].push(
+                salary
+                    .gross
+                    .iter()
+                    .rev()
+                    .find(|&&gross| gross > 0.0)
+                    .map_or(0.0, |gross| gross * 12.0),
+            );
+            salary_totals[1].push(lifetime(&salary.gross));
+            salary_totals[2].push(lifetime(&salary.employee));
+            salary_totals[3].push(lifetime(&salary.employer));
+            for (account, series) in salary.by_account.iter().enumerate() {
+                salary_totals[4 + account].push(lifetime(series));
+            }
+        }
+        // Non-indexed flows follow this path's own cumulative inflation.
+        let mut realized_flows = planned_flows
+            .has_path_indexed_flows()
+            .then(|| RealizedFlows::new(planned_flows));
+        member_death_months_by_sim.push(member_death_months);
+        wage_shocks_by_sim.push(wage_shocks);
         let annual_fee_rate = input.annual_fee_percent.clamp(0.0, 1.0);
-        let tax_on_gains_rate = input.tax_on_gains_percent.clamp(0.0, 1.0);
+        // With account buckets, taxes are charged per account instead of on monthly gains.
+        let tax_on_gains_rate = if account_model.is_some() {
+            0.0
+        } else {
+            input.tax_on_gains_percent.clamp(0.0, 1.0)
+        };
         let monthly_fee_factor = (1.0 - annual_fee_rate / 12.0).max(0.0);
-        let mut regime_state = initial_regime_state(monthly_markov.0, monthly_markov.1, &mut rng);
+        let mut regime_state = initial_regime_state(&monthly_transition, &mut rng);
+        let mut inflation_process = InflationPersistence::new(inflation_persistence);
         let mut annual_real_returns = Vec::new();
         let mut sim_balances = vec![0.0_f64; months as usize];
         let mut sim_growth = vec![1.0_f64; months as usize];
-
-        let mut block_remaining = 0;
+        let mut sim_inflation = vec![0.0_f64; months as usize];
+        let mut sim_price_index = if nominal_output {
+            vec![1.0_f64; months as usize]
+        } else {
+            vec![]
+        };
+        let mut sim_spending = if withdrawal_policy.is_some() {
+            vec![0.0_f64; months as usize]
+        } else {
+            vec![]
+        };
+        let mut realized_annual = Vec::new();
+        let mut planned_annual = Vec::new();
+        let mut realized_year = 0.0;
+        let mut planned_year = 0.0;
+        let mut year_months = 0;
+
+        let mut asset_returns = vec![0.0; asset_count];
+        let mut portfolio_state = targets_by_month
+            .as_ref()
+            .map(|targets| PortfolioState::new(targets.first().map_or(&[], Vec::as_slice)));
+        // Depleted months hold no weights and stay out of the allocation bands.
+        let mut sim_weights = vec![vec![f64::NAN; months as usize]; asset_count];
+        let mut sim_asset_returns = Vec::with_capacity(asset_count * months as usize);
+        let mut sold_fraction = 0.0;
+        let mut sim_sales = if record_sales {
+            vec![0.0_f64; months as usize]
+        } else {
+            vec![]
+        };
+        let mut block_sampler = BlockSampler::new(
+            bootstrap_scheme.unwrap_or(BootstrapScheme::RegimeBlock),
+            block_length,
+        )
+        .starting_at(sim);
         let mut current_history_index = 0;
         let mut active_monthly_asset_return = 0.0;
 
         if !use_monthly_calibration {
-            let pool = if regime_state == 0 {
-                &annual_regime_bootstrap_pool.growth
-            } else {
-                &annual_regime_bootstrap_pool.crisis
-            };
+            let pool = annual_regime_bootstrap_pool.pool(regime_state);
             let random_idx = (rng.random() * pool.len() as f64).floor() as usize;
             active_monthly_asset_return =
                 annual_to_monthly_return(pool[random_idx.min(pool.len().saturating_sub(1))]);
@@ -319,46 +674,41 @@ pub fn run_monte_carlo_simulation(
         let mut annual_inflation = 0.0;
 
         for m in 0..months as usize {
+            if let Some(realized) = realized_flows.as_mut() {
+                // The year ahead is re-deflated at today's index for the annual spending review.
+                let year_end = (m + 12).min(months as usize);
+                realized.advance(planned_flows, m, state.price_index, year_end);
+            }
+            let flows = realized_flows
+                .as_ref()
+                .map_or(planned_flows, |realized| &realized.flows);
             let mut regime_changed = false;
 
-            if m > 0 {
-                let next_regime_state = transition_regime_state(
-                    regime_state,
-                    monthly_markov.0,
-                    monthly_markov.1,
-                    &mut rng,
-                );
+            if m == 0 {
+                regime_changed = true;
+            } else if !regime_from_history {
+                let next_regime_state =
+                    transition_regime_state(regime_state, &monthly_transition, &mut rng);
                 if next_regime_state != regime_state {
                     regime_changed = true;
                     regime_state = next_regime_state;
                 }
-            } else {
-                regime_changed = true;
             }
 
             if use_monthly_calibration {
-                if block_remaining <= 0 || regime_changed {
-                    let index_pool = if regime_state == 0 {
-                        &monthly_regime_bootstrap_indices.growth
-                    } else {
-                        &monthly_regime_bootstrap_indices.crisis
-                    };
-                    let random_idx = (rng.random() * index_pool.len() as f64).floor() as usize;
-                    current_history_index =
-                        index_pool[random_idx.min(index_pool.len().saturating_sub(1))];
-                    block_remaining = block_length;
-                } else {
-                    current_history_index =
-                        (current_history_index + 1) % effective_monthly_history.len();
+                let index_pool = monthly_regime_bootstrap_indices.pool(regime_state);
+                current_history_index = block_sampler.next(
+                    effective_monthly_history.len(),
+                    index_pool,
+                    regime_changed,
+                    &mut rng,
+                );
+                if regime_from_history {
+                    regime_state = monthly_detected_regimes[current_history_index];
                 }
                 active_monthly_asset_return = effective_monthly_history[current_history_index];
-                block_remaining -= 1;
             } else if m > 0 && m % 12 == 0 {
-                let pool = if regime_state == 0 {
-                    &annual_regime_bootstrap_pool.growth
-                } else {
-                    &annual_regime_bootstrap_pool.crisis
-                };
+                let pool = annual_regime_bootstrap_pool.pool(regime_state);
                 let random_idx = (rng.random() * pool.len() as f64).floor() as usize;
                 let sampled_annual_return = pool[random_idx.min(pool.len().saturating_sub(1))];
                 active_monthly_asset_return = annual_to_monthly_return(sampled_annual_return);
@@ -366,29 +716,29 @@ pub fn run_monte_carlo_simulation(
                 annual_inflation = 0.0;
             }
 
-            let stress_drift = if regime_state == 0 {
-                0.0
-            } else {
-                (crisis_mean - growth_mean) * 0.1
-            };
-            let stress_noise = if regime_state == 0 {
-                growth_std * 0.04
-            } else {
-                crisis_std * 0.08
-            };
+            let (stress_drift, stress_noise) = regime_stress[regime_state];
 
-            let monthly_asset_return = if use_monthly_calibration || use_historical_bootstrap {
-                active_monthly_asset_return
-            } else {
-                active_monthly_asset_return
-                    + draw_monthly_return_shaped(
-                        stress_drift,
-                        stress_noise,
-                        input.return_skewness,
-                        input.return_kurtosis,
-                        &mut rng,
-                    )
-            };
+            let monthly_asset_return =
+                if let (Some(portfolio), Some(state)) = (&portfolio, portfolio_state.as_mut()) {
+                    if use_asset_history && use_monthly_calibration {
+                        portfolio.historical_returns(current_history_index, &mut asset_returns);
+                    } else {
+                        portfolio.draw_returns(regime_state, &mut rng, &mut asset_returns);
+                    }
+                    sim_asset_returns.extend_from_slice(&asset_returns);
+                    state.grow(&asset_returns)
+                } else if use_monthly_calibration || use_historical_bootstrap {
+                    active_monthly_asset_return
+                } else {
+                    active_monthly_asset_return
+                        + draw_monthly_return_shaped(
+                            stress_drift,
+                            stress_noise,
+                            input.return_skewness,
+                            input.return_kurtosis,
+                            &mut rng,
+                        )
+                };
 
             let monthly_asset_return_after_tax = if monthly_asset_return > 0.0 {
                 monthly_asset_return * (1.0 - tax_on_gains_rate)
@@ -400,32 +750,86 @@ pub fn run_monte_carlo_simulation(
                 (1.0 + monthly_asset_return_after_tax) * monthly_fee_factor;
             let monthly_portfolio_return_after_costs = monthly_portfolio_growth_factor - 1.0;
 
-            let effective_inflation_mean = if regime_state == 0 {
-                growth_inflation_mean
-            } else {
-                crisis_inflation_mean
+            let (effective_inflation_mean, effective_inflation_variability) =
+                regime_inflation[regime_state];
+            let monthly_inflation = match historical_inflation
+                .get(current_history_index)
+                .filter(|v| v.is_finite())
+            {
+                Some(&inflation) => inflation.max(-0.99),
+                None => inflation_process.apply(
+                    effective_inflation_mean / 12.0,
+                    draw_monthly_return_shaped(
+                        effective_inflation_mean,
+                        effective_inflation_variability,
+                        input.inflation_skewness,
+                        input.inflation_kurtosis,
+                        &mut rng,
+                    ),
+                ),
             };
-            let monthly_inflation = draw_monthly_return_shaped(
-                effective_inflation_mean,
-                input.inflation_variability,
-                input.inflation_skewness,
-                input.inflation_kurtosis,
-                &mut rng,
-            );
 
             annual_asset_return =
                 (1.0 + annual_asset_return) * (1.0 + monthly_portfolio_return_after_costs) - 1.0;
             annual_inflation = (1.0 + annual_inflation) * (1.0 + monthly_inflation) - 1.0;
 
-            balance += monthly_net_flow[m] + lump_sum_by_month[m];
-            balance *= monthly_portfolio_growth_factor;
-            balance /= 1.0 + monthly_inflation;
             sim_growth[m] = monthly_portfolio_growth_factor / (1.0 + monthly_inflation);
+            sim_inflation[m] = monthly_inflation;
+            let month_flows = state.step(&rules, flows, m, sim_growth[m], monthly_inflation, None);
+            let net_flow = month_flows.net_flow;
+            cumulative_shortfall += month_flows.unfunded;
+            if account_model.is_some() {
+                for (account, path) in sim_accounts.iter_mut().enumerate() {
+                    path[m] = state.ledger.balances[account];
+                }
+            }
+            if let Some(spending) = month_flows.spending {
+                if month_flows.reviewed {
+                    if year_months > 0 {
+                        realized_annual.push(realized_year);
+                        planned_annual.push(planned_year);
+                    }
+                    realized_year = 0.0;
+                    planned_year = 0.0;
+                    year_months = 0;
+                }
+                sim_spending[m] = spending * 12.0;
+                if m >= retire_month as usize {
+                    realized_year += spending;
+                    planned_year += flows.monthly_spending[m];
+                    year_months += 1;
+                }
+            }
+            if nominal_output {
+                sim_price_index[m] = state.price_index;
+            }
 
-            if balance <= 0.0 {
-                cumulative_shortfall += (0.0_f64).max(-balance);
+            if let (Some(allocation), Some(targets)) = (
+                portfolio_state.as_mut().filter(|_| state.balance > 0.0),
+                targets_by_month.as_ref().map(|targets| &targets[m]),
+            ) {
+                // Cash flows are treated as arriving at the end of the month for allocation.
+                let flow = net_flow + flows.lump_sum_by_month[m];
+                allocation.apply_flow(
+                    rebalance_policy,
+                    targets,
+                    (state.balance - flow).max(0.0),
+                    flow,
+                );
+                let sold = allocation.rebalance(rebalance_policy, targets, m);
+                state.realize_gains(&rules, sold);
+                if record_sales {
+                    sim_sales[m] = sold;
+                }
+                sold_fraction += sold;
+                for (path, weight) in sim_weights.iter_mut().zip(&allocation.weights) {
+                    path[m] = *weight;
+                }
+            }
+
+            if state.balance <= 0.0 {
                 depleted = true;
-                balance = 0.0;
+                state.balance = 0.0;
             }
 
             if m % 12 == 11 || m == months as usize - 1 {
@@ -433,43 +837,160 @@ pub fn run_monte_carlo_simulation(
                     .push((1.0 + annual_asset_return) / (1.0 + annual_inflation).max(0.0001) - 1.0);
             }
 
-            if balance == 0.0 {
+            if state.balance == 0.0 {
                 depleted_months += 1;
+                first_depletion_month.get_or_insert(m);
             }
-            sim_balances[m] = balance;
+            sim_balances[m] = state.balance;
         }
+        let balance = state.balance;
 
         let retire_index = (retire_month as usize)
             .saturating_sub(1)
             .min((months as usize).saturating_sub(1));
-        retire_balances.push(sim_balances[retire_index]);
+        let pwr_start_balance = match sim_balances.get(retire_index) {
+            Some(&balance) if retire_month > 0 => balance,
+            _ => starting_balance(input),
+        };
+        retire_balances.push(
+            sim_balances
+                .get(retire_index)
+                .copied()
+                .unwrap_or(pwr_start_balance),
+        );
+        let pwr = perfect_withdrawal_rate(
+            pwr_start_balance,
+            sim_growth.get(retire_month as usize..).unwrap_or(&[]),
+            pwr_bequest,
+        );
+        if pwr.is_some() && pwr_start_balance <= 0.0 {
+            pwr_depleted_paths += 1;
+        }
+        perfect_withdrawal_rates.extend(pwr);
         final_balances.push(balance);
         shortfall_totals.push(cumulative_shortfall);
         depleted_years_series.push((depleted_months as f64) / 12.0);
         depleted_flags.push(depleted);
+        depletion_months.push(first_depletion_month);
         annual_real_returns_by_sim.push(annual_real_returns);
+        if sequential {
+            // Labelled by the input month the cohort starts at, past any dropped months.
+            historical_cohorts.push(build_cohort(
+                sim,
+                input
+                    .historical_start_month
+                    .as_deref()
+                    .zip(history_months.get(sim))
+                    .and_then(|(start, &month)| month_label(start, month)),
+                sim + months as usize > effective_monthly_history.len(),
+                &sim_balances,
+                !depleted && balance > 0.0,
+                input.current_age,
+                retire_month as usize,
+            ));
+        }
+        if nominal_output {
+            nominal_balances_by_sim.push(
+                sim_balances
+                    .iter()
+                    .zip(&sim_price_index)
+                    .map(|(balance, index)| balance * index)
+                    .collect(),
+            );
+        }
         all_balances.push(sim_balances);
+        if account_model.is_some() {
+            for (paths, path) in account_balances_by_sim.iter_mut().zip(sim_accounts) {
+                paths.push(path);
+            }
+        }
+        if account_model.is_some() || tax_schedule.is_some() {
+            lifetime_taxes.push(state.ledger.taxes_paid);
+        }
+        if withdrawal_policy.is_some() {
+            if year_months > 0 {
+                let annualize = 12.0 / year_months as f64;
+                realized_annual.push(realized_year * annualize);
+                planned_annual.push(planned_year * annualize);
+            }
+            realized_spending_by_sim.push(realized_annual);
+            planned_spending_by_sim.push(planned_annual);
+            all_spending.push(sim_spending);
+        }
+        // Last month the household is alive for under the mortality success definition.
+        let death_index = death_ages
+            .last()
+            .filter(|_| mortality_success)
+            .map(|&death_age| {
+                (((death_age - input.current_age) * 12.0).max(0.0).floor() as usize)
+                    .min((months as usize).saturating_sub(1))
+            });
+        if portfolio_state.is_some() {
+            for (paths, path) in asset_weights_by_sim.iter_mut().zip(sim_weights) {
+                paths.push(path);
+            }
+            annual_turnover.push(sold_fraction / (months as f64 / 12.0));
+            let allocation_path = AllocationPath {
+                flows: planned_flows,
+                returns: &sim_asset_returns,
+                inflation: &sim_inflation,
+                death_index,
+            };
+            let allocation_candidates = rebalancing_candidates.iter_mut().chain(
+                glide_path_candidates
+                    .iter_mut()
+                    .flatten()
+                    .map(|(_, candidate)| candidate),
+            );
+            for candidate in allocation_candidates {
+                candidate.replay_path(&allocation_replay, &allocation_path);
+            }
+        }
         growth_factors.push(sim_growth);
+        inflation_paths.push(sim_inflation);
+        if record_sales {
+            rebalancing_sales_by_sim.push(sim_sales);
+        }
 
-        if !depleted && balance > 0.0 {
+        // Counted under the reported success definition, which adaptive sampling tests.
+        let succeeded = match death_index {
+            Some(death_index) => first_depletion_month.is_none_or(|month| month > death_index),
+            None => !depleted && balance > 0.0,
+        };
+        if succeeded {
             success_count += 1;
         }
 
         // Report progress every ~10%
         if sim % progress_step == 0 || sim == sim_count - 1 {
             if let Some(cb) = &progress_callback {
-                let progress = (sim + 1) as f64 / sim_count as f64 * 0.9; // 0-90% for sim loop
-                cb(progress);
+                let share = match &adaptive_sampling {
+                    Some(sampling) => sampling.progress(sim + 1, sampling_started),
+                    None => (sim + 1) as f64 / sim_count as f64,
+                };
+                cb(share * 0.9); // 0-90% for sim loop
+            }
+        }
+
+        if let Some(sampling) = &adaptive_sampling {
+            stopped_by = sampling.stop_reason(sim + 1, success_count);
+            if stopped_by.is_some() {
+                break;
             }
         }
     }
+    let sim_count = final_balances.len();
 
     // Report "processing results" phase at 90%
     if let Some(cb) = &progress_callback {
         cb(0.90);
     }
 
-    let target_fi_p95 = find_retirement_balance_target(&retire_balances, &final_balances, 0.95);
+    let target_fi_p95 = find_retirement_balance_target(
+        &retire_balances,
+        &final_balances,
+        FI_TARGET_SUCCESS_PROBABILITY,
+    );
     let target_fi_swr = spending_at_retirement / input.safe_withdrawal_rate.max(0.01);
     let fi_count_p95 = retire_balances
         .iter()
@@ -480,32 +1001,28 @@ pub fn run_monte_carlo_simulation(
         .filter(|&&b| b >= target_fi_swr)
         .count();
 
-    let mut pt10 = Vec::with_capacity(months as usize);
-    let mut pt25 = Vec::with_capacity(months as usize);
-    let mut pt50 = Vec::with_capacity(months as usize);
-    let mut pt75 = Vec::with_capacity(months as usize);
-    let mut pt90 = Vec::with_capacity(months as usize);
-
-    let mut column = vec![0.0; sim_count];
-    for m in 0..months as usize {
-        for s in 0..sim_count {
-            column[s] = all_balances[s][m];
-        }
-        column.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        pt10.push(percentile(&column, 0.1));
-        pt25.push(percentile(&column, 0.25));
-        pt50.push(percentile(&column, 0.5));
-        pt75.push(percentile(&column, 0.75));
-        pt90.push(percentile(&column, 0.9));
-    }
-
-    let percentile_series = PercentileSeries {
-        p10: pt10,
-        p25: pt25,
-        p50: pt50,
-        p75: pt75,
-        p90: pt90,
-    };
+    let percentile_series = percentile_bands(&all_balances, months as usize);
+    let account_percentiles = account_model.as_ref().map(|_| AccountPercentiles {
+        taxable: percentile_bands(&account_balances_by_sim[TAXABLE], months as usize),
+        tax_deferred: percentile_bands(&account_balances_by_sim[TAX_DEFERRED], months as usize),
+        tax_free: percentile_bands(&account_balances_by_sim[TAX_FREE], months as usize),
+    });
+    let allocation_percentiles = portfolio.as_ref().map(|portfolio| {
+        portfolio
+            .assets
+            .iter()
+            .zip(&asset_weights_by_sim)
+            .map(|(asset, paths)| AssetWeightPercentiles {
+                id: asset.id.clone(),
+                weights: percentile_bands(paths, months as usize),
+            })
+            .collect()
+    });
+    let nominal_percentiles =
+        nominal_output.then(|| percentile_bands(&nominal_balances_by_sim, months as usize));
+    let spending_percentiles = withdrawal_policy
+        .as_ref()
+        .map(|_| percentile_bands(&all_spending, months as usize));
 
     let mut ages = Vec::with_capacity(months as usize);
     for i in 0..months {
@@ -520,6 +1037,10 @@ pub fn run_monte_carlo_simulation(
         ages,
         retire_month,
         percentiles: percentile_series,
+        nominal_percentiles,
+        spending_percentiles,
+        account_percentiles,
+        allocation_percentiles,
         final_percentiles: final_percentiles.clone(),
         retire_percentiles: retire_percentiles.clone(),
     };
@@ -532,24 +1053,87 @@ pub fn run_monte_carlo_simulation(
         &depleted_flags,
     );
 
-    let ruin_surface = build_ruin_surface(
-        input,
-        spending_periods,
-        income_sources,
-        lump_sum_events,
-        &growth_factors,
-        months,
-        sim_count,
-    );
+    let household_death_months: Vec<Option<usize>> = death_ages
+        .iter()
+        .map(|&age| {
+            let month = ((age - input.current_age) * 12.0).max(0.0).floor() as usize;
+            Some(month).filter(|&month| month < months as usize)
+        })
+        .collect();
+    // Candidate plans (conversions, stress scenarios, claiming ages, solvers) rerun the month
+    // step over the stored paths.
+    let path_replay = PathReplay {
+        plan,
+        rules,
+        growth_factors: &growth_factors,
+        inflation_paths: &inflation_paths,
+        rebalancing_sales: &rebalancing_sales_by_sim,
+        member_death_months: &member_death_months_by_sim,
+        wage_shocks: &wage_shocks_by_sim,
+        death_months: &household_death_months,
+        mortality_success,
+    };
+
+    let conversion_plan = optimize_conversions(&path_replay);
+    let ruin_surface = build_ruin_surface(&path_replay, sim_count);
+
+    let rebalancing = targets_by_month.as_ref().map(|_| RebalancingStats {
+        policy: rebalance_policy.label(),
+        annual_turnover: summarize(&annual_turnover),
+        comparison: compare_rebalancing_policies(&rebalancing_candidates),
+    });
+    let glide_path_comparison = glide_path_candidates.as_ref().map(|candidates| {
+        compare_glide_paths(
+            candidates,
+            input.retirement_age,
+            input.current_age + months as f64 / 12.0,
+        )
+    });
+
+    let stress_tests = input.stress_test.as_ref().map(|stress_test| {
+        let class_mix = match (&portfolio, &targets_by_month) {
+            (Some(portfolio), Some(targets)) => targets
+                .iter()
+                .map(|weights| portfolio.class_mix(weights))
+                .collect(),
+            _ => StressReplay::single_asset_mix(stress_test, months as usize),
+        };
+        let replay = StressReplay {
+            paths: &path_replay,
+            monthly_fee_factor: allocation_replay.monthly_fee_factor,
+            tax_on_gains_rate: allocation_replay.tax_on_gains_rate,
+            class_mix,
+        };
+        run_stress_tests(&replay, stress_test)
+    });
+
+    let claiming_analysis = analyze_claiming_ages(&path_replay);
+    let solver = input.solver.as_ref().map(|solver| {
+        let replay = SolverReplay {
+            paths: &path_replay,
+        };
+        run_solvers(&replay, solver)
+    });
+
+    let mortality = mortality_enabled.then(|| {
+        build_mortality_stats(
+            &all_balances,
+            &death_ages,
+            &depletion_months,
+            input.current_age,
+        )
+    });
+    let success_probability = (success_count as f64) / (sim_count as f64);
 
     let stats = SummaryStats {
         fi_target: target_fi_p95,
         fi_target_swr: target_fi_swr,
         fi_target_p95: target_fi_p95,
-        success_probability: (success_count as f64) / (sim_count as f64),
+        success_probability,
         fi_probability_swr: (fi_count_swr as f64) / (sim_count as f64),
         fi_probability_p95: (fi_count_p95 as f64) / (sim_count as f64),
         return_moments,
+        regime_fit,
         sequence_risk,
         ruin_surface,
         shortfall_low: shortfall_percentiles.p10,
@@ -564,11 +1148,45 @@ pub fn run_monte_carlo_simulation(
         final_high: final_percentiles.p90,
         retire_median: retire_percentiles.p50,
         retire_high: retire_percentiles.p90,
+        mortality,
+        lifetime_taxes: (!lifetime_taxes.is_empty()).then(|| summarize(&lifetime_taxes)),
+        conversion_plan,
+        claiming_analysis,
+        rebalancing,
+        glide_path_comparison,
+        historical_cohorts: sequential.then_some(historical_cohorts),
+        salary: salary_model.as_ref().map(|_| SalaryStats {
+            final_salary: summarize(&salary_totals[0]),
+            lifetime_salary: summarize(&salary_totals[1]),
+            employee_contributions: summarize(&salary_totals[2]),
+            employer_contributions: summarize(&salary_totals[3]),
+            taxable_contributions: summarize(&salary_totals[4]).p50,
+            tax_deferred_contributions: summarize(&salary_totals[5]).p50,
+            tax_free_contributions: summarize(&salary_totals[6]).p50,
+        }),
+        stress_tests,
+        solver,
+        perfect_withdrawal_rate: (!perfect_withdrawal_rates.is_empty())
+            .then(|| summarize(&perfect_withdrawal_rates)),
+        perfect_withdrawal_rate_depleted_paths: pwr_depleted_paths,
+        convergence: build_convergence_stats(
+            input.convergence.as_ref(),
+            input.seed,
+            success_probability,
+            &retire_balances,
+            &final_balances,
+            stopped_by,
+        ),
     };
 
-    SimulationResultWrapper {
+    let spending_stats = withdrawal_policy
+        .as_ref()
+        .map(|_| build_spending_stats(&realized_spending_by_sim, &planned_spending_by_sim));
+
+    Ok(SimulationResultWrapper {
         simulation,
         stats,
+        spending_stats,
         sim_count: sim_count as u32,
-    }
+    })
 }
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use crate::accounts::starting_balance;
+use crate::engine::{SolverSolution, SolverStats, FI_TARGET_SUCCESS_PROBABILITY};
+use crate::replay::{Candidate, PathReplay, PlanFlows, PlanRules, ReplayOptions};
+use crate::structs::SolverInput;
+use crate::withdrawal::WithdrawalPolicy;
+
+// Two-sided 95% normal quantile.
+const CONFIDENCE_Z: f64 = 1.96;
+const BISECTION_STEPS: usize = 30;
+const MAX_SPENDING_SCALE: f64 = 100.0;
+const MAX_SAVINGS_DOUBLINGS: usize = 40;
+
+/// Candidate plans replayed through the simulation's own month step over its stored paths,
+/// so candidates differ only in the plan itself (common random numbers) and are judged by
+/// the headline success definition.
+pub struct SolverReplay<'a> {
+    pub paths: &'a PathReplay<'a>,
+}
+
+impl SolverReplay<'_> {
+    fn months(&self) -> usize {
+        self.paths.months()
+    }
+
+    fn success_probability(
+        &self,
+        flows: &PlanFlows,
+        rules: &PlanRules,
+        initial_balance: Option<f64>,
+    ) -> f64 {
+        self.paths.success_probability(
+            flows,
+            rules,
+            &ReplayOptions {
+                initial_balance,
+                ..ReplayOptions::default()
+            },
+        )
+    }
+
+    fn candidate_success(&self, candidate: &Candidate) -> f64 {
+        self.success_probability(&candidate.flows, &candidate.rules, None)
+    }
+
+    // Success of the planned flows with every spending amount scaled by `scale`.
+    fn scaled_success(&self, planned: &Candidate, scale: f64) -> f64 {
+        let rules = PlanRules {
+            spending_scale: scale,
+            ..planned.rules
+        };
+        self.success_probability(&planned.flows, &rules, None)
+    }
+
+    /// Earliest retirement age, in whole months, whose success probability reaches `threshold`.
+    fn retirement_age(&self, threshold: f64, cache: &mut HashMap<usize, f64>) -> Option<f64> {
+        let age_at = |month: usize| self.paths.plan.input.current_age + month as f64 / 12.0;
+        let retire_months: Vec<usize> = (0..self.months()).collect();
+        let index = retire_months.partition_point(|&month| {
+            let success = *cache
+                .entry(month)
+                .or_insert_with(|| self.candidate_success(&self.paths.candidate(age_at(month))));
+            success < threshold
+        });
+        retire_months.get(index).map(|&month| age_at(month))
+    }
+
+    /// Largest multiple of planned spending whose success probability reaches `threshold`.
+    fn spending_scale(&self, threshold: f64, planned: &Candidate) -> Option<f64> {
+        let passes = |scale: f64| self.scaled_success(planned, scale) >= threshold;
+        if !passes(0.0) || passes(MAX_SPENDING_SCALE) {
+            return None;
+        }
+        Some(bisect(MAX_SPENDING_SCALE, 0.0, passes))
+    }
+
+    /// Smallest balance today whose success probability reaches `threshold`. With accounts
+    /// the balance keeps the accounts' starting proportions.
+    fn required_savings(&self, threshold: f64, planned: &Candidate) -> Option<f64> {
+        let passes = |balance: f64| {
+            self.success_probability(&planned.flows, &planned.rules, Some(balance)) >= threshold
+        };
+        if passes(0.0) {
+            return Some(0.0);
+        }
+        let mut high = starting_balance(self.paths.plan.input).max(1.0);
+        for _ in 0..MAX_SAVINGS_DOUBLINGS {
+            if passes(high) {
+                return Some(bisect(0.0, high, passes));
+            }
+            high *= 2.0;
+        }
+        None
+    }
+}
+
+/// Narrows the bracket between a `failing` and a `passing` value of a monotone `passes` and
+/// returns its passing end.
+fn bisect(mut failing: f64, mut passing: f64, mut passes: impl FnMut(f64) -> bool) -> f64 {
+    for _ in 0..BISECTION_STEPS {
+        let mid = 0.5 * (failing + passing);
+        if passes(mid) {
+            passing = mid;
+        } else {
+            failing = mid;
+        }
+    }
+    passing
+}
+
+// Solves at the target and at the target ± `CONFIDENCE_Z` standard errors. `increasing` says
+// whether a stricter target moves the answer up (retirement age, savings) or down (spending).
+fn solve_with_interval(
+    target: f64,
+    path_count: usize,
+    increasing: bool,
+    mut solve: impl FnMut(f64) -> Option<f64>,
+    mut success_at: impl FnMut(f64) -> f64,
+) -> SolverSolution {
+    let standard_error = (target * (1.0 - target) / path_count.max(1) as f64).sqrt();
+    let looser = solve((target - CONFIDENCE_Z * standard_error).clamp(0.0, 1.0));
+    let stricter = solve((target + CONFIDENCE_Z * standard_error).clamp(0.0, 1.0));
+    let value = solve(target);
+    let (lower, upper) = if increasing {
+        (looser, stricter)
+    } else {
+        (stricter, looser)
+    };
+    SolverSolution {
+        target_success_probability: target,
+        standard_error,
+        value,
+        success_probability: value.map(&mut success_at),
+        lower,
+        upper,
+    }
+}
+
+/// Root-finds the requested plan parameters over the stored paths.
+pub fn run_solvers(replay: &SolverReplay, solver: &SolverInput) -> SolverStats {
+    let requested = |name: &str| {
+        solver
+            .solve
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|requested| requested == name))
+    };
+    let target = solver
+        .target_success_probability
+        .unwrap_or(FI_TARGET_SUCCESS_PROBABILITY)
+        .clamp(0.0, 1.0);
+    let path_count = replay.paths.path_count();
+    let planned = replay
+        .paths
+        .candidate(replay.paths.plan.input.retirement_age);
+    // Portfolio-percentage modes set spending from the balance, so scaling the planned
+    // spending changes nothing.
+    let spending_scale_unsupported = match replay.paths.rules.withdrawal_policy {
+        Some(WithdrawalPolicy::PortfolioPercentage(_)) => replay
+            .paths
+            .plan
+            .input
+            .withdrawal_policy
+            .as_ref()
+            .and_then(|policy| policy.mode.clone()),
+        _ => None,
+    };
+
+    let retirement_age = requested("retirementAge").then(|| {
+        let mut cache = HashMap::new();
+        solve_with_interval(
+            target,
+            path_count,
+            true,
+            |threshold| replay.retirement_age(threshold, &mut cache),
+            |age| replay.candidate_success(&replay.paths.candidate(age)),
+        )
+    });
+    let spending_scale =
+        (requested("spendingScale") && spending_scale_unsupported.is_none()).then(|| {
+            let spending_target = solver
+                .max_ruin_probability
+                .map_or(target, |ruin| 1.0 - ruin.clamp(0.0, 1.0));
+            solve_with_interval(
+                spending_target,
+                path_count,
+                false,
+                |threshold| replay.spending_scale(threshold, &planned),
+                |scale| replay.scaled_success(&planned, scale),
+            )
+        });
+    let required_savings = requested("requiredSavings").then(|| {
+        solve_with_interval(
+            target,
+            path_count,
+            true,
+            |threshold| replay.required_savings(threshold, &planned),
+            |balance| replay.success_probability(&planned.flows, &planned.rules, Some(balance)),
+        )
+    });
+
+    SolverStats {
+        path_count,
+        retirement_age,
+        spending_scale,
+        spending_scale_unsupported,
+        required_savings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::PlanInputs;
+    use crate::structs::{IncomeSource, RetirementInput, SpendingPeriod, WithdrawalPolicyInput};
+    use crate::withdrawal::PercentageRule;
+
+    const MONTHS: usize = 120;
+
+    // Two flat paths from 60 to 70: a 24,000 salary until retirement at 62 and 12,000 of
+    // spending throughout, so the balance ends at savings + 24 * 2,000 − 120 * 1,000 × scale.
+    fn solve(policy: Option<WithdrawalPolicy>) -> SolverStats {
+        let mut input = RetirementInput::for_tests(60.0, 62.0, 70.0, 60_000.0);
+        if policy.is_some() {
+            input.withdrawal_policy = Some(WithdrawalPolicyInput {
+                mode: Some("constantPercentage".to_string()),
+                initial_withdrawal_rate: None,
+                capital_preservation_threshold: None,
+                capital_preservation_cut: None,
+                capital_preservation_final_years: None,
+                prosperity_threshold: None,
+                prosperity_raise: None,
+                inflation_skip: None,
+                withdrawal_percent: None,
+                equity_allocation: None,
+                horizon_age: None,
+            });
+        }
+        let spending_periods = [SpendingPeriod::for_tests(60.0, 70.0, 12_000.0)];
+        let income_sources = [IncomeSource::for_tests("is-default", 60.0, 62.0, 24_000.0)];
+        let growth_factors = vec![vec![1.0; MONTHS]; 2];
+        let inflation_paths = vec![vec![0.0; MONTHS]; 2];
+        let paths = PathReplay {
+            plan: PlanInputs {
+                input: &input,
+                spending_periods: &spending_periods,
+                income_sources: &income_sources,
+                lump_sum_events: &[],
+                months: MONTHS as u32,
+            },
+            rules: PlanRules {
+                account_model: None,
+                tax_schedule: None,
+                withdrawal_policy: policy.as_ref(),
+                retire_month: 24,
+                current_age: 60.0,
+                spending_scale: 1.0,
+            },
+            growth_factors: &growth_factors,
+            inflation_paths: &inflation_paths,
+            rebalancing_sales: &[],
+            member_death_months: &[],
+            wage_shocks: &[],
+            death_months: &[],
+            mortality_success: false,
+        };
+        let solver = SolverInput {
+            solve: None,
+            target_success_probability: None,
+            max_ruin_probability: None,
+        };
+        run_solvers(&SolverReplay { paths: &paths }, &solver)
+    }
+
+    #[test]
+    fn solvers_find_the_break_even_plan_on_flat_paths() {
+        let stats = solve(None);
+
+        // Retiring after 30 months leaves exactly nothing, so the 31st month is the first that
+        // succeeds.
+        let retirement_age = stats.retirement_age.unwrap();
+        assert!((retirement_age.value.unwrap() - (60.0 + 31.0 / 12.0)).abs() < 1e-9);
+        assert_eq!(retirement_age.success_probability, Some(1.0));
+
+        // 60,000 + 48,000 − 120,000 × scale > 0 below a scale of 0.9.
+        let spending_scale = stats.spending_scale.unwrap().value.unwrap();
+        assert!((spending_scale - 0.9).abs() < 1e-6 && spending_scale < 0.9);
+
+        // savings + 48,000 − 120,000 > 0 above 72,000.
+        let required_savings = stats.required_savings.unwrap().value.unwrap();
+        assert!((required_savings - 72_000.0).abs() < 1e-3 && required_savings > 72_000.0);
+        assert_eq!(stats.spending_scale_unsupported, None);
+    }
+
+    #[test]
+    fn spending_scale_is_unsupported_under_percentage_withdrawals() {
+        let stats = solve(Some(WithdrawalPolicy::PortfolioPercentage(
+            PercentageRule::Constant(0.04),
+        )));
+        assert!(stats.spending_scale.is_none());
+        assert_eq!(
+            stats.spending_scale_unsupported.as_deref(),
+            Some("constantPercentage")
+        );
+    }
+}
@@ -1,7 +1,38 @@
-use crate::calculations::{percentile, summarize};
-use crate::engine::{RuinSurface, SequenceRiskBucket};
-use crate::engine2::build_cashflow_arrays;
-use crate::structs::{IncomeSource, LumpSumEvent, RetirementInput, SpendingPeriod};
+use crate::calculations::summarize;
+use crate::engine::{MortalityStats, RuinContour, RuinSurface, SequenceRiskBucket, SpendingStats};
+use crate::replay::{Candidate, PathReplay, PlanRules, ReplayOptions};
+use crate::structs::{IncomeSource, RuinSurfaceAxisInput};
+
+const DEFAULT_RUIN_SURFACE_STEPS: usize = 5;
+
+/// Constant real yearly withdrawal, as a share of `retirement_balance`, that leaves exactly
+/// `bequest` after the growth factors from retirement to the horizon, with each withdrawal
+/// taken at the start of its month. 0 when nothing is left at retirement, and `None` without
+/// any months left.
+pub fn perfect_withdrawal_rate(
+    retirement_balance: f64,
+    growth_after_retirement: &[f64],
+    bequest: f64,
+) -> Option<f64> {
+    if growth_after_retirement.is_empty() {
+        return None;
+    }
+    if retirement_balance <= 0.0 {
+        return Some(0.0);
+    }
+    // Growth from each month to the horizon, and its sum over all withdrawal months.
+    let mut growth_to_horizon = 1.0;
+    let mut withdrawal_growth = 0.0;
+    for &growth in growth_after_retirement.iter().rev() {
+        growth_to_horizon *= growth;
+        withdrawal_growth += growth_to_horizon;
+    }
+    if withdrawal_growth <= 0.0 {
+        return None;
+    }
+    let monthly_withdrawal = (retirement_balance * growth_to_horizon - bequest) / withdrawal_growth;
+    Some(12.0 * monthly_withdrawal / retirement_balance)
+}
 
 pub fn build_sequence_risk_summary(
     annual_real_returns_by_sim: &[Vec<f64>],
@@ -90,120 +121,168 @@ pub fn build_sequence_risk_summary(
     buckets
 }
 
-pub fn replay_ruin_probability(
-    growth_factors: &[Vec<f64>],
-    monthly_net_flow: &[f64],
-    lump_sum_by_month: &[f64],
-    current_savings: f64,
-    sample_count: usize,
-    months: u32,
-) -> f64 {
-    let mut ruin_count = 0;
-
-    for sim in 0..sample_count {
-        let mut balance = current_savings;
-        let mut ruined = false;
-
-        for month in 0..months as usize {
-            balance += monthly_net_flow[month] + lump_sum_by_month[month];
-            balance *= growth_factors[sim][month];
-            if balance <= 0.0 {
-                balance = 0.0;
-                ruined = true;
-            }
-        }
+/// Income sources for an alternative retirement age of the primary person. Sources linked
+/// with `retirementLink` follow it: `endsAtRetirement` / `startsAtRetirement` through the cash
+/// flow builder, `shiftsWithRetirement` by moving both ages by the change. Unlinked sources keep
+/// their ages, except the default salary source, which ends at the candidate age.
+pub fn income_sources_for_retirement_age(
+    income_sources: &[IncomeSource],
+    planned_retirement_age: f64,
+    retirement_age: f64,
+) -> Vec<IncomeSource> {
+    let shift = retirement_age - planned_retirement_age;
+    income_sources
+        .iter()
+        .map(|source| match source.retirement_link.as_deref() {
+            Some("shiftsWithRetirement") if source.owner.is_none() => IncomeSource {
+                from_age: source.from_age + shift,
+                to_age: source.to_age + shift,
+                ..source.clone()
+            },
+            None if source.id == "is-default" => IncomeSource {
+                to_age: retirement_age,
+                ..source.clone()
+            },
+            _ => source.clone(),
+        })
+        .collect()
+}
 
-        if ruined || balance <= 0.0 {
-            ruin_count += 1;
-        }
+// `steps` evenly spaced values from `min` to `max`, or the explicit `values`.
+fn ruin_surface_axis(axis: Option<&RuinSurfaceAxisInput>, min: f64, max: f64) -> Vec<f64> {
+    if let Some(values) = axis.and_then(|axis| axis.values.clone()) {
+        return values;
     }
-
-    (ruin_count as f64) / (sample_count.max(1) as f64)
+    let min = axis.and_then(|axis| axis.min).unwrap_or(min);
+    let max = axis.and_then(|axis| axis.max).unwrap_or(max);
+    let steps = axis
+        .and_then(|axis| axis.steps)
+        .unwrap_or(DEFAULT_RUIN_SURFACE_STEPS)
+        .max(1);
+    if steps == 1 {
+        return vec![min];
+    }
+    (0..steps)
+        .map(|step| min + (max - min) * step as f64 / (steps - 1) as f64)
+        .collect()
 }
 
-pub fn build_ruin_surface(
-    input: &RetirementInput,
-    spending_periods: &[SpendingPeriod],
-    income_sources: &[IncomeSource],
-    lump_sum_events: &[LumpSumEvent],
-    growth_factors: &[Vec<f64>],
-    months: u32,
-    sim_count: usize,
-) -> RuinSurface {
-    let spending_multipliers = vec![0.8, 0.9, 1.0, 1.1, 1.2];
-
-    let offsets = vec![-6.0, -3.0, 0.0, 3.0, 6.0];
-    let mut retirement_ages: Vec<usize> = offsets
-        .iter()
-        .map(|&offset| {
-            let age = input.retirement_age + offset;
-            (input.simulate_until_age - 1.0)
-                .min((input.current_age + 1.0).max(age))
-                .round() as usize
+// Spending multiplier at which each retirement age's ruin probability crosses
+// `max_ruin_probability`, interpolated linearly between grid rows.
+fn ruin_contour(
+    spending_multipliers: &[f64],
+    ruin_probabilities: &[Vec<f64>],
+    age_count: usize,
+    max_ruin_probability: f64,
+) -> Vec<Option<f64>> {
+    (0..age_count)
+        .map(|age| {
+            spending_multipliers
+                .windows(2)
+                .zip(ruin_probabilities.windows(2))
+                .find_map(|(multipliers, rows)| {
+                    let (low, high) = (rows[0][age], rows[1][age]);
+                    if low > max_ruin_probability || high <= max_ruin_probability {
+                        return None;
+                    }
+                    let weight = (max_ruin_probability - low) / (high - low);
+                    Some(multipliers[0] + weight * (multipliers[1] - multipliers[0]))
+                })
         })
-        .collect();
+        .collect()
+}
 
-    retirement_ages.sort_unstable();
+/// Ruin probability over a grid of retirement ages and spending multipliers, each cell
+/// replayed over the first `sampleSize` paths like the main run, so the cell at the planned
+/// age and a multiplier of 1 is one minus the headline success probability.
+pub fn build_ruin_surface(replay: &PathReplay, sim_count: usize) -> RuinSurface {
+    let input = replay.plan.input;
+    let surface_input = input.ruin_surface.as_ref();
+    let mut spending_multipliers: Vec<f64> = ruin_surface_axis(
+        surface_input.and_then(|surface| surface.spending_multipliers.as_ref()),
+        0.8,
+        1.2,
+    )
+    .into_iter()
+    .map(|multiplier| multiplier.max(0.0))
+    .collect();
+    spending_multipliers.sort_by(f64::total_cmp);
+    spending_multipliers.dedup();
+
+    // Whole months between a year after today and a year before the horizon.
+    let mut retirement_ages: Vec<f64> = ruin_surface_axis(
+        surface_input.and_then(|surface| surface.retirement_ages.as_ref()),
+        input.retirement_age - 6.0,
+        input.retirement_age + 6.0,
+    )
+    .into_iter()
+    .map(|age| {
+        let age = (input.simulate_until_age - 1.0).min((input.current_age + 1.0).max(age));
+        (age * 12.0).round() / 12.0
+    })
+    .collect();
+    retirement_ages.sort_by(f64::total_cmp);
     retirement_ages.dedup();
 
-    let sampled_scenarios = sim_count.min(800);
+    let sampled_scenarios = surface_input
+        .and_then(|surface| surface.sample_size)
+        .unwrap_or(sim_count)
+        .clamp(1, replay.path_count().max(1));
 
+    let options = ReplayOptions {
+        sample_count: Some(sampled_scenarios),
+        ..ReplayOptions::default()
+    };
+    let candidates: Vec<Candidate> = retirement_ages
+        .iter()
+        .map(|&age| replay.candidate(age))
+        .collect();
     let ruin_probabilities: Vec<Vec<f64>> = spending_multipliers
         .iter()
         .map(|&multiplier| {
-            let scaled_spending: Vec<SpendingPeriod> = spending_periods
+            candidates
                 .iter()
-                .map(|period| SpendingPeriod {
-                    yearly_amount: period.yearly_amount * multiplier,
-                    ..period.clone()
+                .map(|candidate| {
+                    let rules = PlanRules {
+                        spending_scale: multiplier,
+                        ..candidate.rules
+                    };
+                    1.0 - replay.success_probability(&candidate.flows, &rules, &options)
                 })
-                .collect();
+                .collect()
+        })
+        .collect();
 
-            retirement_ages
-                .iter()
-                .map(|&ret_age| {
-                    let mut adjusted_input = input.clone();
-                    adjusted_input.retirement_age = ret_age as f64;
-
-                    let adjusted_income: Vec<IncomeSource> = income_sources
-                        .iter()
-                        .map(|source| {
-                            if source.id == "is-default" {
-                                IncomeSource {
-                                    to_age: ret_age as f64,
-                                    ..source.clone()
-                                }
-                            } else {
-                                source.clone()
-                            }
-                        })
-                        .collect();
-
-                    let arrays = build_cashflow_arrays(
-                        &adjusted_input,
-                        &scaled_spending,
-                        &adjusted_income,
-                        lump_sum_events,
-                        months,
-                    );
-
-                    replay_ruin_probability(
-                        growth_factors,
-                        &arrays.monthly_net_flow,
-                        &arrays.lump_sum_by_month,
-                        input.current_savings,
-                        sampled_scenarios,
-                        months,
-                    )
-                })
+    let standard_errors = ruin_probabilities
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&ruin| (ruin * (1.0 - ruin) / sampled_scenarios as f64).sqrt())
                 .collect()
         })
         .collect();
+    let target_contour = surface_input
+        .and_then(|surface| surface.target_success_probability)
+        .map(|target| {
+            let target = target.clamp(0.0, 1.0);
+            RuinContour {
+                target_success_probability: target,
+                spending_multipliers: ruin_contour(
+                    &spending_multipliers,
+                    &ruin_probabilities,
+                    retirement_ages.len(),
+                    1.0 - target,
+                ),
+            }
+        });
 
     RuinSurface {
         retirement_ages,
         spending_multipliers,
         ruin_probabilities,
+        standard_errors,
+        sample_size: sampled_scenarios,
+        target_contour,
     }
 }
 
@@ -257,3 +336,168 @@ pub fn find_retirement_balance_target(
 
     required_target.max(0.0)
 }
+
+pub fn build_spending_stats(
+    realized_annual_by_sim: &[Vec<f64>],
+    planned_annual_by_sim: &[Vec<f64>],
+) -> SpendingStats {
+    let sim_count = realized_annual_by_sim.len().max(1) as f64;
+    let mut mean_annual = Vec::with_capacity(realized_annual_by_sim.len());
+    let mut min_annual = Vec::with_capacity(realized_annual_by_sim.len());
+    let mut cut_count = 0;
+    let mut raise_count = 0;
+
+    for (realized, planned) in realized_annual_by_sim.iter().zip(planned_annual_by_sim) {
+        if realized.is_empty() {
+            mean_annual.push(0.0);
+            min_annual.push(0.0);
+            continue;
+        }
+        mean_annual.push(realized.iter().sum::<f64>() / realized.len() as f64);
+        min_annual.push(realized.iter().cloned().fold(f64::INFINITY, f64::min));
+
+        let ratios = realized
+            .iter()
+            .zip(planned)
+            .filter(|(_, &plan)| plan > 0.0)
+            .map(|(&actual, &plan)| actual / plan);
+        let (mut cut, mut raised) = (false, false);
+        for ratio in ratios {
+            cut |= ratio < 0.999;
+            raised |= ratio > 1.001;
+        }
+        if cut {
+            cut_count += 1;
+        }
+        if raised {
+            raise_count += 1;
+        }
+    }
+
+    SpendingStats {
+        mean_annual: summarize(&mean_annual),
+        min_annual: summarize(&min_annual),
+        cut_probability: cut_count as f64 / sim_count,
+        raise_probability: raise_count as f64 / sim_count,
+    }
+}
+
+pub fn build_mortality_stats(
+    all_balances: &[Vec<f64>],
+    death_ages: &[f64],
+    depletion_months: &[Option<usize>],
+    current_age: f64,
+) -> MortalityStats {
+    let sim_count = all_balances.len().max(1) as f64;
+    let mut ruin_before_death_count = 0;
+    let mut survivor_count = 0;
+    let mut depletion_ages = Vec::new();
+    let mut years_depleted_before_death = Vec::new();
+    let mut bequests = Vec::with_capacity(all_balances.len());
+
+    for ((balances, &death_age), &depletion_month) in
+        all_balances.iter().zip(death_ages).zip(depletion_months)
+    {
+        if balances.is_empty() {
+            continue;
+        }
+        let death_month = ((death_age - current_age) * 12.0).max(0.0).floor() as usize;
+        if death_month >= balances.len() {
+            survivor_count += 1;
+        }
+        let death_index = death_month.min(balances.len() - 1);
+        bequests.push(balances[death_index]);
+
+        if let Some(month) = depletion_month {
+            let depletion_age = current_age + month as f64 / 12.0;
+            depletion_ages.push(depletion_age);
+            if month <= death_index {
+                ruin_before_death_count += 1;
+                years_depleted_before_death.push((death_age - depletion_age).max(0.0));
+            }
+        }
+    }
+
+    MortalityStats {
+        ruin_before_death_probability: ruin_before_death_count as f64 / sim_count,
+        survival_to_horizon_probability: survivor_count as f64 / sim_count,
+        death_age: summarize(death_ages),
+        depletion_age: summarize(&depletion_ages),
+        years_depleted_before_death: summarize(&years_depleted_before_death),
+        expected_bequest: bequests.iter().sum::<f64>() / bequests.len().max(1) as f64,
+        bequest: summarize(&bequests),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perfect_withdrawal_rate_exhausts_a_constant_growth_path() {
+        // Two months at 1% with withdrawals w at the start of each: ((1000 - w) * 1.01 - w) * 1.01
+        // = 0, so w = 1010 / 2.01.
+        let rate = perfect_withdrawal_rate(1000.0, &[1.01, 1.01], 0.0).unwrap();
+        assert!((rate - 12.0 * (1010.0 / 2.01) / 1000.0).abs() < 1e-12);
+
+        // Without growth, a year of withdrawals spends everything but the bequest.
+        let rate = perfect_withdrawal_rate(1000.0, &[1.0; 12], 400.0).unwrap();
+        assert!((rate - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn perfect_withdrawal_rate_is_zero_when_depleted_before_retirement() {
+        assert_eq!(perfect_withdrawal_rate(0.0, &[1.01; 12], 0.0), Some(0.0));
+        assert_eq!(perfect_withdrawal_rate(1000.0, &[], 0.0), None);
+    }
+
+    #[test]
+    fn ruin_surface_axis_spaces_steps_evenly_unless_values_are_given() {
+        assert_eq!(ruin_surface_axis(None, 0.8, 1.2).len(), 5);
+        assert_eq!(
+            ruin_surface_axis(None, 60.0, 64.0),
+            vec![60.0, 61.0, 62.0, 63.0, 64.0]
+        );
+
+        let axis = RuinSurfaceAxisInput {
+            min: Some(1.0),
+            max: None,
+            steps: Some(3),
+            values: None,
+        };
+        assert_eq!(
+            ruin_surface_axis(Some(&axis), 0.0, 2.0),
+            vec![1.0, 1.5, 2.0]
+        );
+
+        let single = RuinSurfaceAxisInput {
+            steps: Some(0),
+            ..axis.clone()
+        };
+        assert_eq!(ruin_surface_axis(Some(&single), 0.0, 2.0), vec![1.0]);
+
+        let explicit = RuinSurfaceAxisInput {
+            values: Some(vec![0.9, 1.1]),
+            ..axis
+        };
+        assert_eq!(ruin_surface_axis(Some(&explicit), 0.0, 2.0), vec![0.9, 1.1]);
+    }
+
+    #[test]
+    fn ruin_contour_interpolates_the_crossing_for_each_age() {
+        let multipliers = [0.8, 1.0, 1.2];
+        // Rows by multiplier, columns by age: the first age crosses 0.1 halfway between 0.8
+        // and 1.0, the second between 1.0 and 1.2, the third is above it everywhere and the
+        // fourth never reaches it.
+        let ruin = vec![
+            vec![0.05, 0.0, 0.2, 0.0],
+            vec![0.15, 0.05, 0.3, 0.02],
+            vec![0.25, 0.3, 0.4, 0.05],
+        ];
+        let contour = ruin_contour(&multipliers, &ruin, 4, 0.1);
+        assert!((contour[0].unwrap() - 0.9).abs() < 1e-12);
+        assert!((contour[1].unwrap() - 1.04).abs() < 1e-12);
+        assert_eq!(contour[2], None);
+        assert_eq!(contour[3], None);
+    }
+}
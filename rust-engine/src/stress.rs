@@ -0,0 +1,231 @@
+use crate::calculations::summarize;
+use crate::engine::{StressScenarioOutcome, StressTestStats};
+use crate::replay::{PathReplay, PlanFlows, ReplayOptions};
+use crate::structs::StressTestInput;
+
+const DEFAULT_EQUITY_SHARE: f64 = 0.6;
+
+// (equity, bond, cash, inflation) for one year.
+type StressYear = (f64, f64, f64, f64);
+
+// US calendar-year total returns (S&P 500, 10-year Treasury, 3-month T-bill) and
+// December-to-December CPI, from the first year of each crisis.
+const BUILT_IN_SCENARIOS: [(&str, [StressYear; 10]); 5] = [
+    (
+        "1929",
+        [
+            (-0.0830, 0.0420, 0.0445, 0.006),
+            (-0.2512, 0.0454, 0.0227, -0.064),
+            (-0.4384, -0.0256, 0.0115, -0.093),
+            (-0.0864, 0.0879, 0.0088, -0.103),
+            (0.4998, 0.0186, 0.0052, 0.008),
+            (-0.0119, 0.0796, 0.0027, 0.015),
+            (0.4674, 0.0447, 0.0017, 0.030),
+            (0.3194, 0.0502, 0.0017, 0.014),
+            (-0.3534, 0.0138, 0.0028, 0.029),
+            (0.2928, 0.0421, 0.0007, -0.028),
+        ],
+    ),
+    (
+        "1966",
+        [
+            (-0.0997, 0.0291, 0.0485, 0.035),
+            (0.2380, -0.0158, 0.0429, 0.030),
+            (0.1081, 0.0327, 0.0534, 0.047),
+            (-0.0824, -0.0501, 0.0667, 0.062),
+            (0.0356, 0.1675, 0.0639, 0.056),
+            (0.1422, 0.0979, 0.0433, 0.033),
+            (0.1876, 0.0282, 0.0406, 0.034),
+            (-0.1431, 0.0366, 0.0704, 0.087),
+            (-0.2590, 0.0199, 0.0785, 0.123),
+            (0.3700, 0.0361, 0.0579, 0.069),
+        ],
+    ),
+    (
+        "1973",
+        [
+            (-0.1431, 0.0366, 0.0704, 0.087),
+            (-0.2590, 0.0199, 0.0785, 0.123),
+            (0.3700, 0.0361, 0.0579, 0.069),
+            (0.2383, 0.1598, 0.0498, 0.049),
+            (-0.0698, 0.0129, 0.0526, 0.067),
+            (0.0651, -0.0078, 0.0718, 0.090),
+            (0.1852, 0.0067, 0.1005, 0.133),
+            (0.3174, -0.0299, 0.1139, 0.125),
+            (-0.0470, 0.0820, 0.1404, 0.089),
+            (0.2042, 0.3281, 0.1060, 0.038),
+        ],
+    ),
+    (
+        "2000",
+        [
+            (-0.0903, 0.1666, 0.0576, 0.034),
+            (-0.1185, 0.0557, 0.0367, 0.016),
+            (-0.2197, 0.1512, 0.0166, 0.024),
+            (0.2836, 0.0038, 0.0103, 0.019),
+            (0.1074, 0.0449, 0.0123, 0.033),
+            (0.0483, 0.0287, 0.0301, 0.034),
+            (0.1561, 0.0196, 0.0468, 0.025),
+            (0.0548, 0.1021, 0.0464, 0.041),
+            (-0.3655, 0.2010, 0.0159, 0.001),
+            (0.2594, -0.1112, 0.0014, 0.027),
+        ],
+    ),
+    (
+        "2008",
+        [
+            (-0.3655, 0.2010, 0.0159, 0.001),
+            (0.2594, -0.1112, 0.0014, 0.027),
+            (0.1482, 0.0846, 0.0013, 0.015),
+            (0.0210, 0.1604, 0.0003, 0.030),
+            (0.1589, 0.0297, 0.0005, 0.017),
+            (0.3215, -0.0910, 0.0007, 0.015),
+            (0.1352, 0.1075, 0.0005, 0.008),
+            (0.0138, 0.0128, 0.0021, 0.007),
+            (0.1177, 0.0069, 0.0051, 0.021),
+            (0.2161, 0.0280, 0.0139, 0.021),
+        ],
+    ),
+];
+
+pub struct StressScenario {
+    pub name: String,
+    years: Vec<StressYear>,
+}
+
+impl StressScenario {
+    /// Built-in scenarios followed by the custom ones, filtered to `scenarios` when given.
+    pub fn from_input(input: &StressTestInput) -> Vec<Self> {
+        let built_in = BUILT_IN_SCENARIOS
+            .iter()
+            .map(|(name, years)| StressScenario {
+                name: name.to_string(),
+                years: years.to_vec(),
+            });
+        let custom = input
+            .custom_scenarios
+            .iter()
+            .flatten()
+            .map(|scenario| StressScenario {
+                name: scenario.name.clone(),
+                years: scenario
+                    .years
+                    .iter()
+                    .map(|year| (year.equity, year.bond, year.cash, year.inflation))
+                    .collect(),
+            });
+        built_in
+            .chain(custom)
+            .filter(|scenario| {
+                input
+                    .scenarios
+                    .as_ref()
+                    .is_none_or(|names| names.contains(&scenario.name))
+            })
+            .collect()
+    }
+}
+
+/// The simulation's stored paths, replayed through its month step with the months from
+/// retirement on overwritten by a scenario. Scenario months weight the asset classes by the
+/// target allocation, not by the weights the path had drifted to.
+pub struct StressReplay<'a> {
+    pub paths: &'a PathReplay<'a>,
+    pub monthly_fee_factor: f64,
+    pub tax_on_gains_rate: f64,
+    // (equity, bond, cash) shares of the portfolio in each month.
+    pub class_mix: Vec<[f64; 3]>,
+}
+
+impl StressReplay<'_> {
+    /// Equity share of a portfolio without native assets.
+    pub fn single_asset_mix(input: &StressTestInput, months: usize) -> Vec<[f64; 3]> {
+        let equity = input
+            .equity_share
+            .unwrap_or(DEFAULT_EQUITY_SHARE)
+            .clamp(0.0, 1.0);
+        vec![[equity, 1.0 - equity, 0.0]; months]
+    }
+
+    // Real monthly growth factors and monthly inflation for the scenario months, starting at
+    // retirement.
+    fn scenario_months(&self, scenario: &StressScenario, years: usize) -> Vec<(f64, f64)> {
+        let months = self.class_mix.len();
+        let retire_month = self.paths.rules.retire_month;
+        (retire_month..months.min(retire_month + years * 12))
+            .map(|month| {
+                let (equity, bond, cash, inflation) = scenario.years[(month - retire_month) / 12];
+                let mix = self.class_mix[month];
+                let annual_return = mix[0] * equity + mix[1] * bond + mix[2] * cash;
+                let monthly_return = (1.0 + annual_return).max(0.0).powf(1.0 / 12.0) - 1.0;
+                let monthly_return_after_tax = if monthly_return > 0.0 {
+                    monthly_return * (1.0 - self.tax_on_gains_rate)
+                } else {
+                    monthly_return
+                };
+                let monthly_inflation = (1.0 + inflation).max(0.0001).powf(1.0 / 12.0) - 1.0;
+                (
+                    (1.0 + monthly_return_after_tax) * self.monthly_fee_factor
+                        / (1.0 + monthly_inflation),
+                    monthly_inflation,
+                )
+            })
+            .collect()
+    }
+
+    // Success probability and terminal balances with `forced` replacing the months from
+    // retirement on.
+    fn run(&self, flows: &PlanFlows, forced: &[(f64, f64)]) -> (f64, Vec<f64>) {
+        let paths = self.paths;
+        let options = ReplayOptions {
+            forced,
+            ..ReplayOptions::default()
+        };
+        let mut success_count = 0;
+        let mut terminal_balances = Vec::with_capacity(paths.path_count());
+        for sim in 0..paths.path_count() {
+            let outcome = paths.run_path(sim, flows.path(sim), &paths.rules, &options);
+            if paths.succeeded(sim, &outcome) {
+                success_count += 1;
+            }
+            terminal_balances.push(outcome.state.balance);
+        }
+        let success_probability = success_count as f64 / paths.path_count().max(1) as f64;
+        (success_probability, terminal_balances)
+    }
+}
+
+/// Success probability conditional on each scenario hitting the first years of retirement,
+/// next to the unconditional replay of the same paths, which reproduces the headline run.
+pub fn run_stress_tests(replay: &StressReplay, input: &StressTestInput) -> StressTestStats {
+    let flows = replay.paths.flows(&replay.paths.plan);
+    let (unconditional_success_probability, unconditional_balances) = replay.run(&flows, &[]);
+    let scenarios = StressScenario::from_input(input)
+        .iter()
+        .filter(|scenario| !scenario.years.is_empty())
+        .map(|scenario| {
+            let years = input
+                .years
+                .map_or(scenario.years.len(), |years| {
+                    years.max(0.0).round() as usize
+                })
+                .min(scenario.years.len());
+            let (success_probability, terminal_balances) =
+                replay.run(&flows, &replay.scenario_months(scenario, years));
+            let terminal = summarize(&terminal_balances);
+            StressScenarioOutcome {
+                name: scenario.name.clone(),
+                years,
+                success_probability,
+                p10_terminal_balance: terminal.p10,
+                median_terminal_balance: terminal.p50,
+            }
+        })
+        .collect();
+
+    StressTestStats {
+        unconditional_success_probability,
+        unconditional_median_terminal_balance: summarize(&unconditional_balances).p50,
+        scenarios,
+    }
+}
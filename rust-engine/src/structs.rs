@@ -1,6 +1,3 @@
-use crate::calculations::RandomSource;
-use crate::engine::*;
-
 // Let's create `structs.rs` to hold all Svelte<->Rust payload API boundary types to avoid cluttering engine.rs
 
 use serde::{Deserialize, Serialize};
@@ -17,6 +14,22 @@ pub struct SpendingPeriod {
     pub yearly_amount: f64,
     #[serde(rename = "inflationAdjusted")]
     pub inflation_adjusted: Option<bool>,
+    pub indexation: Option<IndexationInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexationInput {
+    // "cpi" (default), "fixed" or "tripleLock".
+    pub mode: Option<String>,
+    pub cap: Option<f64>,
+    pub floor: Option<f64>,
+    // Subtracted from CPI before the cap and floor (CPI − X).
+    pub minus: Option<f64>,
+    #[serde(rename = "lagMonths")]
+    pub lag_months: Option<f64>,
+    // Real earnings growth for the triple lock's earnings leg.
+    #[serde(rename = "earningsGrowth")]
+    pub earnings_growth: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -31,6 +44,34 @@ pub struct IncomeSource {
     pub yearly_amount: f64,
     #[serde(rename = "inflationAdjusted")]
     pub inflation_adjusted: Option<bool>,
+    pub owner: Option<String>,
+    #[serde(rename = "survivorFraction")]
+    pub survivor_fraction: Option<f64>,
+    #[serde(rename = "retirementLink")]
+    pub retirement_link: Option<String>,
+    pub taxable: Option<bool>,
+    pub claiming: Option<ClaimingInput>,
+    pub indexation: Option<IndexationInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClaimingFactorInput {
+    pub age: f64,
+    pub factor: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClaimingInput {
+    #[serde(rename = "claimingAge")]
+    pub claiming_age: Option<f64>,
+    #[serde(rename = "fullRetirementAge")]
+    pub full_retirement_age: Option<f64>,
+    #[serde(rename = "earliestAge")]
+    pub earliest_age: Option<f64>,
+    #[serde(rename = "latestAge")]
+    pub latest_age: Option<f64>,
+    #[serde(rename = "adjustmentFactors")]
+    pub adjustment_factors: Option<Vec<ClaimingFactorInput>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -41,6 +82,17 @@ pub struct LumpSumEvent {
     pub amount: f64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RegimeStateInput {
+    pub name: Option<String>,
+    pub mean: f64,
+    pub std: f64,
+    #[serde(rename = "inflationMean")]
+    pub inflation_mean: Option<f64>,
+    #[serde(rename = "inflationVariability")]
+    pub inflation_variability: Option<f64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RegimeModelInput {
     #[serde(rename = "stayGrowth")]
@@ -55,6 +107,333 @@ pub struct RegimeModelInput {
     pub crisis_mean: f64,
     #[serde(rename = "crisisStd")]
     pub crisis_std: f64,
+    pub states: Option<Vec<RegimeStateInput>>,
+    #[serde(rename = "transitionMatrix")]
+    pub transition_matrix: Option<Vec<Vec<f64>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WithdrawalPolicyInput {
+    pub mode: Option<String>,
+    #[serde(rename = "initialWithdrawalRate")]
+    pub initial_withdrawal_rate: Option<f64>,
+    #[serde(rename = "capitalPreservationThreshold")]
+    pub capital_preservation_threshold: Option<f64>,
+    #[serde(rename = "capitalPreservationCut")]
+    pub capital_preservation_cut: Option<f64>,
+    #[serde(rename = "capitalPreservationFinalYears")]
+    pub capital_preservation_final_years: Option<f64>,
+    #[serde(rename = "prosperityThreshold")]
+    pub prosperity_threshold: Option<f64>,
+    #[serde(rename = "prosperityRaise")]
+    pub prosperity_raise: Option<f64>,
+    #[serde(rename = "inflationSkip")]
+    pub inflation_skip: Option<bool>,
+    #[serde(rename = "withdrawalPercent")]
+    pub withdrawal_percent: Option<f64>,
+    #[serde(rename = "equityAllocation")]
+    pub equity_allocation: Option<f64>,
+    #[serde(rename = "horizonAge")]
+    pub horizon_age: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MortalityInput {
+    pub sex: Option<String>,
+    pub country: Option<String>,
+    #[serde(rename = "tableType")]
+    pub table_type: Option<String>,
+    #[serde(rename = "improvementRate")]
+    pub improvement_rate: Option<f64>,
+    #[serde(rename = "lifeTable")]
+    pub life_table: Option<String>,
+    #[serde(rename = "successDefinition")]
+    pub success_definition: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HouseholdMember {
+    pub id: String,
+    pub label: String,
+    #[serde(rename = "currentAge")]
+    pub current_age: f64,
+    #[serde(rename = "retirementAge")]
+    pub retirement_age: f64,
+    pub mortality: Option<MortalityInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HouseholdInput {
+    pub members: Vec<HouseholdMember>,
+    #[serde(rename = "survivorSpendingRatio")]
+    pub survivor_spending_ratio: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccountInput {
+    pub balance: f64,
+    #[serde(rename = "annualDrag")]
+    pub annual_drag: Option<f64>,
+    #[serde(rename = "withdrawalTaxRate")]
+    pub withdrawal_tax_rate: Option<f64>,
+    #[serde(rename = "costBasis")]
+    pub cost_basis: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccountsInput {
+    pub taxable: Option<AccountInput>,
+    #[serde(rename = "taxDeferred")]
+    pub tax_deferred: Option<AccountInput>,
+    #[serde(rename = "taxFree")]
+    pub tax_free: Option<AccountInput>,
+    #[serde(rename = "withdrawalOrder")]
+    pub withdrawal_order: Option<String>,
+    #[serde(rename = "bracketFillAmount")]
+    pub bracket_fill_amount: Option<f64>,
+    #[serde(rename = "bracketFillRate")]
+    pub bracket_fill_rate: Option<f64>,
+    #[serde(rename = "contributionAccount")]
+    pub contribution_account: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CareerBreakInput {
+    #[serde(rename = "fromAge")]
+    pub from_age: f64,
+    #[serde(rename = "toAge")]
+    pub to_age: f64,
+    // Share of the full salary earned meanwhile: 0 for a break, e.g. 0.6 for part-time.
+    #[serde(rename = "workFraction")]
+    pub work_fraction: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ContributionLimitsInput {
+    pub taxable: Option<f64>,
+    #[serde(rename = "taxDeferred")]
+    pub tax_deferred: Option<f64>,
+    #[serde(rename = "taxFree")]
+    pub tax_free: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SalaryInput {
+    #[serde(rename = "yearlyAmount")]
+    pub yearly_amount: f64,
+    #[serde(rename = "realGrowth")]
+    pub real_growth: Option<f64>,
+    // Standard deviation of the yearly real raise; 0 keeps wage growth deterministic.
+    #[serde(rename = "growthVariability")]
+    pub growth_variability: Option<f64>,
+    #[serde(rename = "untilAge")]
+    pub until_age: Option<f64>,
+    #[serde(rename = "contributionRate")]
+    pub contribution_rate: f64,
+    #[serde(rename = "contributionAccount")]
+    pub contribution_account: Option<String>,
+    // Employer pays `employerMatchRate` per unit contributed, on contributions up to
+    // `employerMatchCap` of salary (default no cap).
+    #[serde(rename = "employerMatchRate")]
+    pub employer_match_rate: Option<f64>,
+    #[serde(rename = "employerMatchCap")]
+    pub employer_match_cap: Option<f64>,
+    #[serde(rename = "employerAccount")]
+    pub employer_account: Option<String>,
+    #[serde(rename = "contributionLimits")]
+    pub contribution_limits: Option<ContributionLimitsInput>,
+    #[serde(rename = "careerBreaks")]
+    pub career_breaks: Option<Vec<CareerBreakInput>>,
+    // Whether the salary enters the income-tax base (default true).
+    pub taxable: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TaxBracketInput {
+    pub threshold: f64,
+    pub rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TaxScheduleInput {
+    pub preset: Option<String>,
+    pub brackets: Option<Vec<TaxBracketInput>>,
+    #[serde(rename = "capitalGainsBrackets")]
+    pub capital_gains_brackets: Option<Vec<TaxBracketInput>>,
+    #[serde(rename = "standardAllowance")]
+    pub standard_allowance: Option<f64>,
+    // Yearly gains exempt on their own instead of sharing the standard allowance.
+    #[serde(rename = "capitalGainsAllowance")]
+    pub capital_gains_allowance: Option<f64>,
+    #[serde(rename = "indexToInflation")]
+    pub index_to_inflation: Option<bool>,
+    #[serde(rename = "flatRate")]
+    pub flat_rate: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssetInput {
+    pub id: String,
+    pub weight: f64,
+    #[serde(rename = "meanReturn")]
+    pub mean_return: Option<f64>,
+    #[serde(rename = "returnVariability")]
+    pub return_variability: Option<f64>,
+    #[serde(rename = "returnSkewness")]
+    pub return_skewness: Option<f64>,
+    #[serde(rename = "returnKurtosis")]
+    pub return_kurtosis: Option<f64>,
+    #[serde(rename = "crisisMeanReturn")]
+    pub crisis_mean_return: Option<f64>,
+    #[serde(rename = "crisisReturnVariability")]
+    pub crisis_return_variability: Option<f64>,
+    #[serde(rename = "historicalMonthlyReturns")]
+    pub historical_monthly_returns: Option<Vec<f64>>,
+    #[serde(rename = "regimeMeanReturns")]
+    pub regime_mean_returns: Option<Vec<f64>>,
+    #[serde(rename = "regimeReturnVariabilities")]
+    pub regime_return_variabilities: Option<Vec<f64>>,
+}
+
+// One calendar year of a stress scenario: nominal total returns and inflation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StressYearInput {
+    pub equity: f64,
+    pub bond: f64,
+    pub cash: f64,
+    pub inflation: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StressScenarioInput {
+    pub name: String,
+    pub years: Vec<StressYearInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StressTestInput {
+    // Scenario names to run; all built-in and custom scenarios when absent.
+    pub scenarios: Option<Vec<String>>,
+    // Years after retirement forced onto the scenario; its full length when absent.
+    pub years: Option<f64>,
+    // Equity share of a single-asset portfolio; the rest is held in bonds.
+    #[serde(rename = "equityShare")]
+    pub equity_share: Option<f64>,
+    #[serde(rename = "customScenarios")]
+    pub custom_scenarios: Option<Vec<StressScenarioInput>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RebalancingInput {
+    pub mode: Option<String>,
+    #[serde(rename = "toleranceBand")]
+    pub tolerance_band: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GlidePathPointInput {
+    pub age: f64,
+    #[serde(rename = "equityWeight")]
+    pub equity_weight: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GlidePathInput {
+    pub name: Option<String>,
+    pub points: Option<Vec<GlidePathPointInput>>,
+    #[serde(rename = "startEquity")]
+    pub start_equity: Option<f64>,
+    #[serde(rename = "retirementEquity")]
+    pub retirement_equity: Option<f64>,
+    #[serde(rename = "endEquity")]
+    pub end_equity: Option<f64>,
+    #[serde(rename = "yearsBeforeRetirement")]
+    pub years_before_retirement: Option<f64>,
+    #[serde(rename = "yearsAfterRetirement")]
+    pub years_after_retirement: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PortfolioInput {
+    pub assets: Vec<AssetInput>,
+    pub correlations: Option<Vec<Vec<f64>>>,
+    pub rebalancing: Option<RebalancingInput>,
+    #[serde(rename = "glidePath")]
+    pub glide_path: Option<GlidePathInput>,
+    #[serde(rename = "glidePathComparison")]
+    pub glide_path_comparison: Option<Vec<GlidePathInput>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConversionOptimizerInput {
+    pub objective: Option<String>,
+    #[serde(rename = "fromAge")]
+    pub from_age: Option<f64>,
+    #[serde(rename = "toAge")]
+    pub to_age: Option<f64>,
+    #[serde(rename = "maxAnnualAmount")]
+    pub max_annual_amount: Option<f64>,
+    pub step: Option<f64>,
+    #[serde(rename = "sampleCount")]
+    pub sample_count: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RuinSurfaceAxisInput {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub steps: Option<usize>,
+    // Explicit grid values; override `min`, `max` and `steps`.
+    pub values: Option<Vec<f64>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RuinSurfaceInput {
+    #[serde(rename = "retirementAges")]
+    pub retirement_ages: Option<RuinSurfaceAxisInput>,
+    #[serde(rename = "spendingMultipliers")]
+    pub spending_multipliers: Option<RuinSurfaceAxisInput>,
+    #[serde(rename = "sampleSize")]
+    pub sample_size: Option<usize>,
+    #[serde(rename = "targetSuccessProbability")]
+    pub target_success_probability: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConvergenceInput {
+    // Wilson half-width of the success probability that ends adaptive sampling; off when omitted.
+    pub tolerance: Option<f64>,
+    #[serde(rename = "maxSimulations")]
+    pub max_simulations: Option<f64>,
+    #[serde(rename = "maxSeconds")]
+    pub max_seconds: Option<f64>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: Option<f64>,
+    // Path resamples for the bootstrap intervals; off when omitted or 0.
+    #[serde(rename = "bootstrapResamples")]
+    pub bootstrap_resamples: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SensitivityInput {
+    // Amounts and volatilities are scaled by 1 ± this; 0.1 when omitted.
+    #[serde(rename = "relativeChange")]
+    pub relative_change: Option<f64>,
+    // Parameter names to perturb ("meanReturn", "spending:<id>", ...); all when omitted.
+    pub parameters: Option<Vec<String>>,
+    // Paths per run; `simulations` when omitted.
+    pub simulations: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SolverInput {
+    // "retirementAge", "spendingScale" and/or "requiredSavings"; all when omitted.
+    pub solve: Option<Vec<String>>,
+    #[serde(rename = "targetSuccessProbability")]
+    pub target_success_probability: Option<f64>,
+    // Ruin ceiling for the spending solve; 1 − the target success probability when omitted.
+    #[serde(rename = "maxRuinProbability")]
+    pub max_ruin_probability: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -95,6 +474,9 @@ pub struct RetirementInput {
     pub inflation_kurtosis: f64,
     #[serde(rename = "inflationCrisisSpread")]
     pub inflation_crisis_spread: Option<f64>,
+    // Year-over-year AR(1) coefficient of parametric inflation draws; 0 is independent.
+    #[serde(rename = "inflationPersistence")]
+    pub inflation_persistence: Option<f64>,
 
     #[serde(rename = "blockLength")]
     pub block_length: Option<usize>,
@@ -111,9 +493,140 @@ pub struct RetirementInput {
 
     #[serde(rename = "regimeModel")]
     pub regime_model: RegimeModelInput,
+    #[serde(rename = "withdrawalPolicy")]
+    pub withdrawal_policy: Option<WithdrawalPolicyInput>,
+    pub mortality: Option<MortalityInput>,
+    pub household: Option<HouseholdInput>,
+    pub accounts: Option<AccountsInput>,
+    #[serde(rename = "taxSchedule")]
+    pub tax_schedule: Option<TaxScheduleInput>,
+    #[serde(rename = "conversionOptimizer")]
+    pub conversion_optimizer: Option<ConversionOptimizerInput>,
+    pub portfolio: Option<PortfolioInput>,
+    pub salary: Option<SalaryInput>,
+    #[serde(rename = "nominalOutput")]
+    pub nominal_output: Option<bool>,
+    #[serde(rename = "stressTest")]
+    pub stress_test: Option<StressTestInput>,
+    pub solver: Option<SolverInput>,
+    #[serde(rename = "ruinSurface")]
+    pub ruin_surface: Option<RuinSurfaceInput>,
+    pub sensitivity: Option<SensitivityInput>,
+    pub convergence: Option<ConvergenceInput>,
+    // Real balance the perfect withdrawal rate leaves at the horizon; 0 when omitted.
+    #[serde(rename = "pwrBequest")]
+    pub pwr_bequest: Option<f64>,
 
     #[serde(rename = "historicalAnnualReturns")]
     pub historical_annual_returns: Option<Vec<f64>>,
     #[serde(rename = "historicalMonthlyReturns")]
     pub historical_monthly_returns: Option<Vec<f64>>,
+    // Monthly inflation aligned month by month with the return history.
+    #[serde(rename = "historicalMonthlyInflation")]
+    pub historical_monthly_inflation: Option<Vec<f64>>,
+    // "YYYY-MM" of the first month of the history, used to label cohorts.
+    #[serde(rename = "historicalStartMonth")]
+    pub historical_start_month: Option<String>,
+}
+
+#[cfg(test)]
+impl RetirementInput {
+    /// A single person with flat return and inflation assumptions and every optional model
+    /// switched off.
+    pub(crate) fn for_tests(
+        current_age: f64,
+        retirement_age: f64,
+        simulate_until_age: f64,
+        current_savings: f64,
+    ) -> Self {
+        RetirementInput {
+            simulation_mode: None,
+            historical_moment_targeting: None,
+            current_age,
+            retirement_age,
+            simulate_until_age,
+            current_savings,
+            mean_return: 0.0,
+            return_variability: 0.0,
+            return_skewness: 0.0,
+            return_kurtosis: 3.0,
+            equity_bond_correlation: 0.0,
+            inflation_mean: 0.0,
+            inflation_variability: 0.0,
+            inflation_skewness: 0.0,
+            inflation_kurtosis: 3.0,
+            inflation_crisis_spread: None,
+            inflation_persistence: None,
+            block_length: None,
+            annual_fee_percent: 0.0,
+            tax_on_gains_percent: 0.0,
+            seed: Some(1.0),
+            safe_withdrawal_rate: 0.04,
+            simulations: 1.0,
+            regime_model: RegimeModelInput {
+                stay_growth: 0.9,
+                stay_crisis: 0.7,
+                growth_mean: 0.0,
+                growth_std: 0.0,
+                crisis_mean: 0.0,
+                crisis_std: 0.0,
+                states: None,
+                transition_matrix: None,
+            },
+            withdrawal_policy: None,
+            mortality: None,
+            household: None,
+            accounts: None,
+            tax_schedule: None,
+            conversion_optimizer: None,
+            portfolio: None,
+            salary: None,
+            nominal_output: None,
+            stress_test: None,
+            solver: None,
+            ruin_surface: None,
+            sensitivity: None,
+            convergence: None,
+            pwr_bequest: None,
+            historical_annual_returns: None,
+            historical_monthly_returns: None,
+            historical_monthly_inflation: None,
+            historical_start_month: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl SpendingPeriod {
+    pub(crate) fn for_tests(from_age: f64, to_age: f64, yearly_amount: f64) -> Self {
+        SpendingPeriod {
+            id: "spending".to_string(),
+            label: "Spending".to_string(),
+            from_age,
+            to_age,
+            yearly_amount,
+            inflation_adjusted: Some(true),
+            indexation: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl IncomeSource {
+    pub(crate) fn for_tests(id: &str, from_age: f64, to_age: f64, yearly_amount: f64) -> Self {
+        IncomeSource {
+            id: id.to_string(),
+            label: id.to_string(),
+            from_age,
+            to_age,
+            yearly_amount,
+            inflation_adjusted: Some(true),
+            owner: None,
+            survivor_fraction: None,
+            retirement_link: None,
+            taxable: None,
+            claiming: None,
+            indexation: None,
+        }
+    }
 }
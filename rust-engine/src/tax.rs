@@ -0,0 +1,338 @@
+use crate::structs::{TaxBracketInput, TaxScheduleInput};
+
+const DEFAULT_FLAT_RATE: f64 = 0.2;
+const MAX_MARGINAL_RATE: f64 = 0.95;
+const GROSS_UP_ITERATIONS: usize = 16;
+
+// 2024 US federal brackets for a single filer, on income above the standard deduction.
+const US_FEDERAL_ALLOWANCE: f64 = 14_600.0;
+const US_FEDERAL_BRACKETS: [(f64, f64); 7] = [
+    (0.0, 0.10),
+    (11_600.0, 0.12),
+    (47_150.0, 0.22),
+    (100_525.0, 0.24),
+    (191_950.0, 0.32),
+    (243_725.0, 0.35),
+    (609_350.0, 0.37),
+];
+const US_FEDERAL_GAINS_BRACKETS: [(f64, f64); 3] =
+    [(0.0, 0.0), (47_025.0, 0.15), (518_900.0, 0.20)];
+
+// 2024/25 UK income tax bands above the personal allowance (allowance taper not modelled).
+const UK_ALLOWANCE: f64 = 12_570.0;
+const UK_BRACKETS: [(f64, f64); 3] = [(0.0, 0.20), (37_700.0, 0.40), (112_570.0, 0.45)];
+const UK_GAINS_BRACKETS: [(f64, f64); 2] = [(0.0, 0.18), (37_700.0, 0.24)];
+// Capital gains annual exempt amount; the personal allowance does not shelter gains.
+const UK_GAINS_ALLOWANCE: f64 = 3_000.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TaxBracket {
+    pub threshold: f64,
+    pub rate: f64,
+}
+
+/// Progressive schedule in today's money. Thresholds apply to income above the allowance;
+/// realized gains are stacked on top of ordinary income.
+#[derive(Clone, Debug)]
+pub struct TaxSchedule {
+    pub brackets: Vec<TaxBracket>,
+    pub gains_brackets: Option<Vec<TaxBracket>>,
+    pub standard_allowance: f64,
+    // Gains exempt on their own each year, in place of any allowance left over by ordinary
+    // income; `None` when gains share the standard allowance.
+    pub gains_allowance: Option<f64>,
+    pub index_to_inflation: bool,
+}
+
+/// Income and gains accumulated over one tax year of a path.
+#[derive(Clone, Debug)]
+pub struct TaxYear {
+    pub ordinary_income: f64,
+    pub realized_gains: f64,
+    // Real value of the schedule's thresholds this year (below 1 when they are not indexed).
+    threshold_scale: f64,
+}
+
+fn brackets_from(table: &[(f64, f64)]) -> Vec<TaxBracket> {
+    table
+        .iter()
+        .map(|&(threshold, rate)| TaxBracket { threshold, rate })
+        .collect()
+}
+
+fn brackets_from_input(inputs: &[TaxBracketInput]) -> Vec<TaxBracket> {
+    let mut brackets: Vec<TaxBracket> = inputs
+        .iter()
+        .filter(|bracket| bracket.threshold.is_finite() && bracket.rate.is_finite())
+        .map(|bracket| TaxBracket {
+            threshold: bracket.threshold.max(0.0),
+            rate: bracket.rate.clamp(0.0, MAX_MARGINAL_RATE),
+        })
+        .collect();
+    brackets.sort_by(|a, b| a.threshold.total_cmp(&b.threshold));
+    brackets
+}
+
+/// Tax on the slice of taxable income between `from` and `to`.
+fn bracket_tax(brackets: &[TaxBracket], from: f64, to: f64, scale: f64) -> f64 {
+    brackets
+        .iter()
+        .enumerate()
+        .map(|(index, bracket)| {
+            let lower = bracket.threshold * scale;
+            let upper = brackets
+                .get(index + 1)
+                .map_or(f64::INFINITY, |next| next.threshold * scale);
+            (to.min(upper) - from.max(lower)).max(0.0) * bracket.rate
+        })
+        .sum()
+}
+
+impl TaxSchedule {
+    pub fn preset(name: &str) -> Option<Self> {
+        let (allowance, brackets, gains_brackets, gains_allowance) = match name {
+            "usFederal" => (
+                US_FEDERAL_ALLOWANCE,
+                brackets_from(&US_FEDERAL_BRACKETS),
+                Some(brackets_from(&US_FEDERAL_GAINS_BRACKETS)),
+                None,
+            ),
+            "uk" => (
+                UK_ALLOWANCE,
+                brackets_from(&UK_BRACKETS),
+                Some(brackets_from(&UK_GAINS_BRACKETS)),
+                Some(UK_GAINS_ALLOWANCE),
+            ),
+            "flat" => (0.0, brackets_from(&[(0.0, DEFAULT_FLAT_RATE)]), None, None),
+            _ => return None,
+        };
+        Some(Self {
+            brackets,
+            gains_brackets,
+            standard_allowance: allowance,
+            gains_allowance,
+            index_to_inflation: true,
+        })
+    }
+
+    /// Starts from the named preset (flat if none) and applies any explicit overrides.
+    pub fn from_input(input: &TaxScheduleInput) -> Self {
+        let mut schedule = input
+            .preset
+            .as_deref()
+            .and_then(Self::preset)
+            .or_else(|| Self::preset("flat"))
+            .expect("flat preset exists");
+
+        if let Some(rate) = input.flat_rate {
+            schedule.brackets = vec![TaxBracket {
+                threshold: 0.0,
+                rate: rate.clamp(0.0, MAX_MARGINAL_RATE),
+            }];
+            schedule.gains_brackets = None;
+        }
+        if let Some(brackets) = input.brackets.as_deref() {
+            schedule.brackets = brackets_from_input(brackets);
+        }
+        if let Some(brackets) = input.capital_gains_brackets.as_deref() {
+            schedule.gains_brackets = Some(brackets_from_input(brackets));
+        }
+        if let Some(allowance) = input.standard_allowance {
+            schedule.standard_allowance = allowance.max(0.0);
+        }
+        if let Some(allowance) = input.capital_gains_allowance {
+            schedule.gains_allowance = Some(allowance.max(0.0));
+        }
+        if let Some(indexed) = input.index_to_inflation {
+            schedule.index_to_inflation = indexed;
+        }
+        schedule
+    }
+
+    /// Total tax for a year's ordinary income and realized gains. Gains are sheltered by the
+    /// gains allowance if the schedule has one, otherwise by any allowance left over after
+    /// ordinary income.
+    pub fn annual_tax(&self, ordinary_income: f64, realized_gains: f64, scale: f64) -> f64 {
+        let allowance = self.standard_allowance * scale;
+        let taxable_income = (ordinary_income - allowance).max(0.0);
+        let gains_allowance = self
+            .gains_allowance
+            .map_or((allowance - ordinary_income).max(0.0), |exempt| {
+                exempt * scale
+            });
+        let taxable_gains = (realized_gains - gains_allowance).max(0.0);
+        let gains_brackets = self.gains_brackets.as_deref().unwrap_or(&self.brackets);
+
+        bracket_tax(&self.brackets, 0.0, taxable_income, scale)
+            + bracket_tax(
+                gains_brackets,
+                taxable_income,
+                taxable_income + taxable_gains,
+                scale,
+            )
+    }
+
+    /// Gross ordinary income at which the bracket taxed at `rate` ends, if there is one.
+    pub fn bracket_ceiling(&self, rate: f64) -> Option<f64> {
+        let index = self
+            .brackets
+            .iter()
+            .position(|bracket| (bracket.rate - rate).abs() < 1e-9)?;
+        self.brackets
+            .get(index + 1)
+            .map(|next| next.threshold + self.standard_allowance)
+    }
+}
+
+impl Default for TaxYear {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaxYear {
+    pub fn new() -> Self {
+        Self {
+            ordinary_income: 0.0,
+            realized_gains: 0.0,
+            threshold_scale: 1.0,
+        }
+    }
+
+    /// `price_index` is the path's cumulative inflation since the start of the simulation.
+    pub fn start(&mut self, schedule: &TaxSchedule, price_index: f64) {
+        self.ordinary_income = 0.0;
+        self.realized_gains = 0.0;
+        self.threshold_scale = if schedule.index_to_inflation || price_index <= 0.0 {
+            1.0
+        } else {
+            1.0 / price_index
+        };
+    }
+
+    pub fn threshold_scale(&self) -> f64 {
+        self.threshold_scale
+    }
+
+    fn tax_on(&self, schedule: &TaxSchedule, ordinary_income: f64, realized_gains: f64) -> f64 {
+        schedule.annual_tax(
+            self.ordinary_income + ordinary_income,
+            self.realized_gains + realized_gains,
+            self.threshold_scale,
+        ) - schedule.annual_tax(
+            self.ordinary_income,
+            self.realized_gains,
+            self.threshold_scale,
+        )
+    }
+
+    /// Adds income to the year and returns the extra tax it triggers.
+    pub fn add(
+        &mut self,
+        schedule: &TaxSchedule,
+        ordinary_income: f64,
+        realized_gains: f64,
+    ) -> f64 {
+        let tax = self.tax_on(schedule, ordinary_income, realized_gains);
+        self.ordinary_income += ordinary_income;
+        self.realized_gains += realized_gains;
+        tax
+    }
+
+    /// Gross sale that nets `amount` after tax when `ordinary_share` of it is ordinary income
+    /// and `gains_share` is realized gain. Tax is piecewise linear in the sale, so Newton steps
+    /// from below land on the exact amount after crossing at most one kink per iteration.
+    pub fn gross_up(
+        &self,
+        schedule: &TaxSchedule,
+        amount: f64,
+        ordinary_share: f64,
+        gains_share: f64,
+    ) -> f64 {
+        let net_of =
+            |gross: f64| gross - self.tax_on(schedule, gross * ordinary_share, gross * gains_share);
+        let mut gross = amount;
+        for _ in 0..GROSS_UP_ITERATIONS {
+            let shortfall = amount - net_of(gross);
+            if shortfall <= 1e-9 * amount.max(1.0) {
+                break;
+            }
+            let slope = (net_of(gross + 1.0) - net_of(gross)).max(1.0 - MAX_MARGINAL_RATE);
+            gross += shortfall / slope;
+        }
+        gross
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> TaxSchedule {
+        TaxSchedule::preset(name).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn us_federal_2024_tax_on_income_and_gains() {
+        let us = preset("usFederal");
+        // 85,400 taxable: 11,600 at 10%, 35,550 at 12%, 38,250 at 22%.
+        assert_close(us.annual_tax(100_000.0, 0.0, 1.0), 13_841.0);
+        // 15,400 taxable income pays 1,160 + 456; gains stacked on it fill the 0% band to
+        // 47,025 and the last 8,375 pay 15%.
+        assert_close(us.annual_tax(30_000.0, 40_000.0, 1.0), 1_616.0 + 1_256.25);
+        // 4,600 of unused deduction shelters gains: 55,400 taxable gains, 8,375 above the 0% band.
+        assert_close(us.annual_tax(10_000.0, 60_000.0, 1.0), 1_256.25);
+    }
+
+    #[test]
+    fn uk_2024_25_tax_on_income_and_gains() {
+        let uk = preset("uk");
+        // 47,430 taxable: 37,700 at 20%, 9,730 at 40%.
+        assert_close(uk.annual_tax(60_000.0, 0.0, 1.0), 11_432.0);
+        // 27,430 taxable income pays 5,486; gains above the 3,000 exempt amount use the
+        // 10,270 left in the basic band at 18% and pay 24% on the other 6,730.
+        assert_close(
+            uk.annual_tax(40_000.0, 20_000.0, 1.0),
+            5_486.0 + 1_848.6 + 1_615.2,
+        );
+        // Personal allowance left over by income does not shelter gains.
+        assert_close(uk.annual_tax(5_000.0, 10_000.0, 1.0), 0.18 * 7_000.0);
+        // Unindexed thresholds worth half as much in real terms.
+        assert_close(
+            uk.annual_tax(60_000.0, 0.0, 0.5),
+            0.2 * 18_850.0 + 0.4 * (60_000.0 - 6_285.0 - 18_850.0),
+        );
+    }
+
+    #[test]
+    fn gross_up_nets_the_amount_across_bracket_kinks() {
+        let us = preset("usFederal");
+        let year = TaxYear::new();
+        // Inside the 12% band: G - (0.12 (G - 14,600) - 232) = 50,000.
+        assert_close(year.gross_up(&us, 50_000.0, 1.0, 0.0), 48_016.0 / 0.88);
+
+        // 45,400 taxable already leaves 1,750 at 12%; the rest of the net amount is grossed up
+        // at 22%.
+        let mut year = TaxYear::new();
+        year.add(&us, 60_000.0, 0.0);
+        let gross = year.gross_up(&us, 10_000.0, 1.0, 0.0);
+        assert_close(gross, 1_750.0 + (10_000.0 - 0.88 * 1_750.0) / 0.78);
+        assert_close(gross - year.add(&us, gross, 0.0), 10_000.0);
+
+        // A UK sale that is half gain: G - 0.18 (G / 2 - 3,000) = 20,000.
+        let uk = preset("uk");
+        assert_close(
+            TaxYear::new().gross_up(&uk, 20_000.0, 0.0, 0.5),
+            19_460.0 / 0.91,
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::structs::RetirementInput;
+
+const DEFAULT_GUARDRAIL_THRESHOLD: f64 = 0.2;
+const DEFAULT_GUARDRAIL_ADJUSTMENT: f64 = 0.1;
+const DEFAULT_CAPITAL_PRESERVATION_FINAL_YEARS: f64 = 15.0;
+const DEFAULT_CONSTANT_WITHDRAWAL_PERCENT: f64 = 0.04;
+const DEFAULT_VPW_HORIZON_AGE: f64 = 100.0;
+const DEFAULT_VPW_EQUITY_ALLOCATION: f64 = 0.6;
+
+// Growth assumptions behind the Bogleheads VPW table (real, annual).
+const VPW_EQUITY_RETURN: f64 = 0.05;
+const VPW_BOND_RETURN: f64 = 0.018;
+
+// IRS Uniform Lifetime Table (2022) divisors for ages 72..=120.
+const RMD_FIRST_AGE: usize = 72;
+const RMD_DIVISORS: [f64; 49] = [
+    27.4, 26.5, 25.5, 24.6, 23.7, 22.9, 22.0, 21.1, 20.2, 19.4, 18.5, 17.7, 16.8, 16.0, 15.2, 14.4,
+    13.7, 12.9, 12.2, 11.5, 10.8, 10.1, 9.5, 8.9, 8.4, 7.8, 7.3, 6.8, 6.4, 6.0, 5.6, 5.2, 4.9, 4.6,
+    4.3, 4.1, 3.9, 3.7, 3.5, 3.4, 3.3, 3.1, 3.0, 2.9, 2.8, 2.7, 2.5, 2.3, 2.0,
+];
+
+pub struct GuardrailPolicy {
+    pub initial_withdrawal_rate: Option<f64>,
+    pub capital_preservation_threshold: f64,
+    pub capital_preservation_cut: f64,
+    pub capital_preservation_final_years: f64,
+    pub prosperity_threshold: f64,
+    pub prosperity_raise: f64,
+    pub inflation_skip: bool,
+    pub horizon_age: f64,
+}
+
+pub enum PercentageRule {
+    Vpw {
+        equity_allocation: f64,
+        horizon_age: f64,
+    },
+    Constant(f64),
+    RemainingYears {
+        horizon_age: f64,
+    },
+    Rmd,
+}
+
+pub enum WithdrawalPolicy {
+    Guardrails(GuardrailPolicy),
+    PortfolioPercentage(PercentageRule),
+}
+
+impl WithdrawalPolicy {
+    pub fn from_input(input: &RetirementInput) -> Option<Self> {
+        let policy = input.withdrawal_policy.as_ref()?;
+        match policy.mode.as_deref().unwrap_or("fixed") {
+            "guardrails" => Some(WithdrawalPolicy::Guardrails(GuardrailPolicy {
+                initial_withdrawal_rate: policy
+                    .initial_withdrawal_rate
+                    .filter(|rate| rate.is_finite() && *rate > 0.0),
+                capital_preservation_threshold: policy
+                    .capital_preservation_threshold
+                    .unwrap_or(DEFAULT_GUARDRAIL_THRESHOLD)
+                    .max(0.0),
+                capital_preservation_cut: policy
+                    .capital_preservation_cut
+                    .unwrap_or(DEFAULT_GUARDRAIL_ADJUSTMENT)
+                    .clamp(0.0, 1.0),
+                capital_preservation_final_years: policy
+                    .capital_preservation_final_years
+                    .unwrap_or(DEFAULT_CAPITAL_PRESERVATION_FINAL_YEARS)
+                    .max(0.0),
+                prosperity_threshold: policy
+                    .prosperity_threshold
+                    .unwrap_or(DEFAULT_GUARDRAIL_THRESHOLD)
+                    .clamp(0.0, 1.0),
+                prosperity_raise: policy
+                    .prosperity_raise
+                    .unwrap_or(DEFAULT_GUARDRAIL_ADJUSTMENT)
+                    .max(0.0),
+                inflation_skip: policy.inflation_skip.unwrap_or(true),
+                horizon_age: input.simulate_until_age,
+            })),
+            "vpw" => Some(WithdrawalPolicy::PortfolioPercentage(PercentageRule::Vpw {
+                equity_allocation: policy
+                    .equity_allocation
+                    .unwrap_or(DEFAULT_VPW_EQUITY_ALLOCATION)
+                    .clamp(0.0, 1.0),
+                horizon_age: policy.horizon_age.unwrap_or(DEFAULT_VPW_HORIZON_AGE),
+            })),
+            "constantPercentage" => Some(WithdrawalPolicy::PortfolioPercentage(
+                PercentageRule::Constant(
+                    policy
+                        .withdrawal_percent
+                        .unwrap_or(DEFAULT_CONSTANT_WITHDRAWAL_PERCENT)
+                        .clamp(0.0, 1.0),
+                ),
+            )),
+            "oneOverN" => Some(WithdrawalPolicy::PortfolioPercentage(
+                PercentageRule::RemainingYears {
+                    horizon_age: policy.horizon_age.unwrap_or(input.simulate_until_age),
+                },
+            )),
+            "rmd" => Some(WithdrawalPolicy::PortfolioPercentage(PercentageRule::Rmd)),
+            _ => None,
+        }
+    }
+}
+
+/// Bogleheads-style VPW rate: the level annual payment (taken at the start of each year)
+/// that exhausts the portfolio at `horizon_age` given the blended growth assumption.
+pub fn vpw_withdrawal_rate(age: f64, equity_allocation: f64, horizon_age: f64) -> f64 {
+    let years = (horizon_age - age.floor()).max(1.0);
+    let rate = equity_allocation * VPW_EQUITY_RETURN + (1.0 - equity_allocation) * VPW_BOND_RETURN;
+    if rate.abs() < 1e-9 {
+        return 1.0 / years;
+    }
+    rate / ((1.0 + rate) * (1.0 - (1.0 + rate).powf(-years)))
+}
+
+pub fn rmd_divisor(age: f64) -> f64 {
+    let whole_age = age.floor().max(0.0) as usize;
+    if whole_age >= RMD_FIRST_AGE {
+        let index = (whole_age - RMD_FIRST_AGE).min(RMD_DIVISORS.len() - 1);
+        return RMD_DIVISORS[index];
+    }
+    // Below the table, extend the divisor by the slope of its first decade.
+    let slope = (RMD_DIVISORS[0] - RMD_DIVISORS[10]) / 10.0;
+    RMD_DIVISORS[0] + slope * (RMD_FIRST_AGE - whole_age) as f64
+}
+
+impl PercentageRule {
+    pub fn withdrawal_rate(&self, age: f64) -> f64 {
+        let rate = match self {
+            PercentageRule::Vpw {
+                equity_allocation,
+                horizon_age,
+            } => vpw_withdrawal_rate(age, *equity_allocation, *horizon_age),
+            PercentageRule::Constant(rate) => *rate,
+            PercentageRule::RemainingYears { horizon_age } => {
+                1.0 / (horizon_age - age.floor()).max(1.0)
+            }
+            PercentageRule::Rmd => 1.0 / rmd_divisor(age),
+        };
+        rate.clamp(0.0, 1.0)
+    }
+}
+
+/// Per-path spending state, reviewed once a year from the retirement month onwards.
+pub struct WithdrawalState {
+    pub scale: f64,
+    portfolio_withdrawal: Option<f64>,
+    initial_rate: Option<f64>,
+    trailing_return: f64,
+    trailing_inflation: f64,
+}
+
+impl Default for WithdrawalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WithdrawalState {
+    pub fn new() -> Self {
+        Self {
+            scale: 1.0,
+            portfolio_withdrawal: None,
+            initial_rate: None,
+            trailing_return: 0.0,
+            trailing_inflation: 0.0,
+        }
+    }
+
+    pub fn record_month(&mut self, nominal_return: f64, inflation: f64) {
+        self.trailing_return = (1.0 + self.trailing_return) * (1.0 + nominal_return) - 1.0;
+        self.trailing_inflation = (1.0 + self.trailing_inflation) * (1.0 + inflation) - 1.0;
+    }
+
+    /// `planned_spending` and `planned_income` are the real amounts scheduled for the
+    /// coming twelve months before any policy adjustment.
+    pub fn review(
+        &mut self,
+        policy: &WithdrawalPolicy,
+        balance: f64,
+        planned_spending: f64,
+        planned_income: f64,
+        age: f64,
+    ) {
+        let trailing_return = self.trailing_return;
+        let trailing_inflation = self.trailing_inflation;
+        self.trailing_return = 0.0;
+        self.trailing_inflation = 0.0;
+
+        match policy {
+            WithdrawalPolicy::Guardrails(guardrails) => {
+                if balance <= 0.0 {
+                    return;
+                }
+                let withdrawal_rate =
+                    |scale: f64| (planned_spending * scale - planned_income).max(0.0) / balance;
+
+                let initial_rate = match self.initial_rate {
+                    Some(rate) => rate,
+                    None => {
+                        let rate = guardrails
+                            .initial_withdrawal_rate
+                            .unwrap_or_else(|| withdrawal_rate(self.scale));
+                        self.initial_rate = Some(rate);
+                        return;
+                    }
+                };
+                if initial_rate <= 0.0 {
+                    return;
+                }
+
+                // Inflation rule: after a losing year, forgo that year's inflation increase,
+                // which in real terms is a cut by the realized inflation.
+                if guardrails.inflation_skip
+                    && trailing_return < 0.0
+                    && trailing_inflation > 0.0
+                    && withdrawal_rate(self.scale) > initial_rate
+                {
+                    self.scale /= 1.0 + trailing_inflation;
+                }
+
+                let current_rate = withdrawal_rate(self.scale);
+                let years_remaining = guardrails.horizon_age - age;
+                if current_rate > initial_rate * (1.0 + guardrails.capital_preservation_threshold)
+                    && years_remaining > guardrails.capital_preservation_final_years
+                {
+                    self.scale *= 1.0 - guardrails.capital_preservation_cut;
+                } else if current_rate < initial_rate * (1.0 - guardrails.prosperity_threshold) {
+                    self.scale *= 1.0 + guardrails.prosperity_raise;
+                }
+            }
+            WithdrawalPolicy::PortfolioPercentage(rule) => {
+                self.portfolio_withdrawal = Some(balance.max(0.0) * rule.withdrawal_rate(age));
+            }
+        }
+    }
+
+    /// Real spending for one month. Portfolio-percentage modes replace the planned amount
+    /// with the reviewed portfolio draw on top of that month's `net_income` (after income
+    /// tax), so the portfolio pays exactly the draw.
+    pub fn monthly_spending(&self, planned_spending: f64, net_income: f64) -> f64 {
+        match self.portfolio_withdrawal {
+            Some(withdrawal) => withdrawal / 12.0 + net_income,
+            None => planned_spending * self.scale,
+        }
+    }
+}